@@ -0,0 +1,410 @@
+//! A-normal form intermediate representation.
+//!
+//! Every argument of an application and every `if` condition is an `Atom`,
+//! and intermediate results are named by typed `let`s. Each binder gets a
+//! unique id, so variables are plain numbers instead of de Bruijn indices.
+
+use std::collections::HashMap;
+
+use core::{Term, Ty, TyLit};
+use errors::*;
+use typecheck::{Judgement, TypeCheckVisitor};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Atom {
+    Var(usize),
+    Int(i64),
+    Bool(bool),
+    Not,
+    Lam(Vec<(usize, Ty)>, Box<Expr>),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Comp {
+    Atom(Atom),
+    App(Atom, Vec<Atom>),
+    If(Atom, Box<Expr>, Box<Expr>),
+    Stuck,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Expr {
+    Let(usize, Ty, Comp, Box<Expr>),
+    Comp(Comp),
+}
+
+struct Converter {
+    next: usize,
+    /// Ids and types of the source variables in scope, innermost last.
+    env: Vec<(usize, Ty)>,
+}
+
+type Binds = Vec<(usize, Ty, Comp)>;
+
+impl Converter {
+    fn fresh(&mut self) -> usize {
+        self.next += 1;
+        self.next - 1
+    }
+
+    fn expr(&mut self, t: &Term) -> Result<(Expr, Ty)> {
+        let mut binds = Binds::new();
+        let (c, ty) = self.comp(t, &mut binds)?;
+
+        let e = binds.into_iter()
+            .rev()
+            .fold(Expr::Comp(c),
+                  |e, (id, ty, c)| Expr::Let(id, ty, c, Box::new(e)));
+        Ok((e, ty))
+    }
+
+    fn comp(&mut self, t: &Term, binds: &mut Binds) -> Result<(Comp, Ty)> {
+        match *t {
+            Term::App(ref f, ref args) => {
+                let (f, fty) = self.atom(f, binds)?;
+                let mut xs = Vec::new();
+                for arg in args {
+                    let (x, _) = self.atom(arg, binds)?;
+                    xs.push(x);
+                }
+
+                match fty {
+                    Ty::Arrow(_, res) => Ok((Comp::App(f, xs), *res)),
+                    _ => unreachable!("the term typechecks"),
+                }
+            }
+            Term::If(ref cond, ref b1, ref b2) => {
+                let (c, _) = self.atom(cond, binds)?;
+                let (e1, t1) = self.expr(b1)?;
+                let (e2, _) = self.expr(b2)?;
+
+                Ok((Comp::If(c, Box::new(e1), Box::new(e2)), t1))
+            }
+            Term::Stuck => Ok((Comp::Stuck, Ty::Bottom)),
+            Term::Hole(ref x) => bail!("Cannot compile the unfilled hole ?{}", x),
+            _ => {
+                let (a, ty) = self.atom(t, binds)?;
+                Ok((Comp::Atom(a), ty))
+            }
+        }
+    }
+
+    fn atom(&mut self, t: &Term, binds: &mut Binds) -> Result<(Atom, Ty)> {
+        match *t {
+            Term::Int(n) => Ok((Atom::Int(n), TyLit::Int.into())),
+            Term::True => Ok((Atom::Bool(true), TyLit::Bool.into())),
+            Term::False => Ok((Atom::Bool(false), TyLit::Bool.into())),
+            Term::Not => {
+                Ok((Atom::Not, Ty::arrow(&[TyLit::Bool.into()], TyLit::Bool.into())))
+            }
+            Term::Var(k) => {
+                let (id, ref ty) = self.env[self.env.len() - k as usize];
+                Ok((Atom::Var(id), ty.clone()))
+            }
+            Term::Abs(ref tys, ref body) => {
+                let params: Vec<(usize, Ty)> =
+                    tys.iter().map(|ty| (self.fresh(), ty.clone())).collect();
+
+                self.env.extend(params.iter().cloned());
+                let res = self.expr(body);
+                let len = self.env.len() - tys.len();
+                self.env.truncate(len);
+
+                let (body, res) = res?;
+                Ok((Atom::Lam(params, Box::new(body)), Ty::arrow(tys, res)))
+            }
+            _ => {
+                let (c, ty) = self.comp(t, binds)?;
+                let id = self.fresh();
+                binds.push((id, ty.clone(), c));
+                Ok((Atom::Var(id), ty))
+            }
+        }
+    }
+}
+
+/// Converts a closed term to ANF, returning it with its type. The term is
+/// typechecked first, so its `TypeError` is what an ill-typed term fails with.
+pub fn convert(t: &Term) -> Result<(Expr, Ty)> {
+    TypeCheckVisitor::new().type_of(t)?;
+
+    let mut c = Converter {
+        next: 0,
+        env: Vec::new(),
+    };
+    c.expr(t)
+}
+
+impl Atom {
+    fn pretty(&self, indent: usize) -> String {
+        match *self {
+            Atom::Var(id) => format!("x{}", id),
+            Atom::Int(n) => n.to_string(),
+            Atom::Bool(true) => "#T".into(),
+            Atom::Bool(false) => "#F".into(),
+            Atom::Not => "!".into(),
+            Atom::Lam(ref params, ref body) => {
+                let params: Vec<String> = params.iter()
+                    .map(|&(id, ref ty)| format!("x{}: {}", id, ty))
+                    .collect();
+                format!("(/lam {}.\n{}{})",
+                        params.join(", "),
+                        pad(indent + 1),
+                        body.pretty_at(indent + 1))
+            }
+        }
+    }
+}
+
+impl Comp {
+    fn pretty(&self, indent: usize) -> String {
+        match *self {
+            Comp::Atom(ref a) => a.pretty(indent),
+            Comp::App(ref f, ref args) => {
+                let args: Vec<String> = args.iter().map(|a| a.pretty(indent)).collect();
+                format!("({} {})", f.pretty(indent), args.join(" "))
+            }
+            Comp::If(ref c, ref b1, ref b2) => {
+                format!("if {}\n{}then {}\n{}else {}",
+                        c.pretty(indent),
+                        pad(indent + 1),
+                        b1.pretty_at(indent + 2),
+                        pad(indent + 1),
+                        b2.pretty_at(indent + 2))
+            }
+            Comp::Stuck => "_|_".into(),
+        }
+    }
+}
+
+fn pad(indent: usize) -> String {
+    "  ".repeat(indent)
+}
+
+impl Expr {
+    pub fn pretty(&self) -> String {
+        self.pretty_at(0)
+    }
+
+    fn pretty_at(&self, indent: usize) -> String {
+        let mut out = String::new();
+        let mut e = self;
+
+        while let Expr::Let(id, ref ty, ref c, ref body) = *e {
+            out.push_str(&format!("let x{}: {} = {} in\n{}", id, ty, c.pretty(indent), pad(indent)));
+            e = body;
+        }
+
+        if let Expr::Comp(ref c) = *e {
+            out.push_str(&c.pretty(indent));
+        }
+
+        out
+    }
+}
+
+/// The expression as a `core::Term`, each `let` becoming an immediately
+/// applied abstraction.
+pub fn to_core(e: &Expr) -> Term {
+    fn var(id: usize, scope: &[usize]) -> Term {
+        // No binder has index 0, so an unbound id stays an unknown variable.
+        scope.iter()
+            .rposition(|&x| x == id)
+            .map_or(Term::Var(0), |i| Term::Var((scope.len() - i) as u32))
+    }
+
+    fn atom(a: &Atom, scope: &mut Vec<usize>) -> Term {
+        match *a {
+            Atom::Var(id) => var(id, scope),
+            Atom::Int(n) => Term::Int(n),
+            Atom::Bool(true) => Term::True,
+            Atom::Bool(false) => Term::False,
+            Atom::Not => Term::Not,
+            Atom::Lam(ref params, ref body) => {
+                let tys: Vec<Ty> = params.iter().map(|&(_, ref ty)| ty.clone()).collect();
+                scope.extend(params.iter().map(|&(id, _)| id));
+                let body = expr(body, scope);
+                let len = scope.len() - params.len();
+                scope.truncate(len);
+                Term::abs(&tys, body)
+            }
+        }
+    }
+
+    fn comp(c: &Comp, scope: &mut Vec<usize>) -> Term {
+        match *c {
+            Comp::Atom(ref a) => atom(a, scope),
+            Comp::App(ref f, ref args) => {
+                Term::App(Box::new(atom(f, scope)),
+                          args.iter().map(|a| atom(a, scope)).collect())
+            }
+            Comp::If(ref c, ref b1, ref b2) => {
+                Term::if_(atom(c, scope), expr(b1, scope), expr(b2, scope))
+            }
+            Comp::Stuck => Term::Stuck,
+        }
+    }
+
+    fn expr(e: &Expr, scope: &mut Vec<usize>) -> Term {
+        match *e {
+            Expr::Comp(ref c) => comp(c, scope),
+            Expr::Let(id, ref ty, ref c, ref body) => {
+                let c = comp(c, scope);
+                scope.push(id);
+                let body = expr(body, scope);
+                scope.pop();
+                Term::app(Term::abs(&[ty.clone()], body), &[c])
+            }
+        }
+    }
+
+    expr(e, &mut Vec::new())
+}
+
+/// Recomputes the type of an ANF expression from its annotations, by
+/// checking `to_core(e)`. A `let` whose annotation does not match its
+/// binding is an argument mismatch.
+pub fn type_of(e: &Expr) -> Judgement {
+    TypeCheckVisitor::new().type_of(&to_core(e))
+}
+
+#[derive(Clone, Debug)]
+pub enum Value<'a> {
+    Bool(bool),
+    Int(i64),
+    Not,
+    Closure(&'a [(usize, Ty)], &'a Expr, Env<'a>),
+}
+
+pub type Env<'a> = HashMap<usize, Value<'a>>;
+
+impl<'a> Value<'a> {
+    /// The value as a term, if it is first-order.
+    pub fn to_term(&self) -> Option<Term> {
+        match *self {
+            Value::Bool(true) => Some(Term::True),
+            Value::Bool(false) => Some(Term::False),
+            Value::Int(n) => Some(Term::Int(n)),
+            Value::Not => Some(Term::Not),
+            Value::Closure(..) => None,
+        }
+    }
+}
+
+pub fn run(e: &Expr) -> Result<Value> {
+    eval(e, &mut Env::new())
+}
+
+fn eval_atom<'a>(a: &'a Atom, env: &Env<'a>) -> Result<Value<'a>> {
+    match *a {
+        Atom::Var(id) => env.get(&id).cloned().ok_or(format!("unbound x{}", id).into()),
+        Atom::Int(n) => Ok(Value::Int(n)),
+        Atom::Bool(b) => Ok(Value::Bool(b)),
+        Atom::Not => Ok(Value::Not),
+        Atom::Lam(ref params, ref body) => Ok(Value::Closure(params, body, env.clone())),
+    }
+}
+
+fn eval_comp<'a>(c: &'a Comp, env: &mut Env<'a>) -> Result<Value<'a>> {
+    match *c {
+        Comp::Atom(ref a) => eval_atom(a, env),
+        Comp::Stuck => bail!("reached bottom"),
+        Comp::If(ref cond, ref b1, ref b2) => {
+            match eval_atom(cond, env)? {
+                Value::Bool(true) => eval(b1, env),
+                Value::Bool(false) => eval(b2, env),
+                v => bail!("if condition {:?} is not a boolean", v),
+            }
+        }
+        Comp::App(ref f, ref args) => {
+            let xs = args.iter().map(|a| eval_atom(a, env)).collect::<Result<Vec<_>>>()?;
+
+            match eval_atom(f, env)? {
+                Value::Closure(params, body, mut cenv) => {
+                    if params.len() != xs.len() {
+                        bail!("expected {} argument(s), found {}", params.len(), xs.len());
+                    }
+                    for (&(id, _), x) in params.iter().zip(xs) {
+                        cenv.insert(id, x);
+                    }
+                    eval(body, &mut cenv)
+                }
+                Value::Not => {
+                    match (xs.len(), xs.first()) {
+                        (1, Some(&Value::Bool(b))) => Ok(Value::Bool(!b)),
+                        _ => bail!("negated something other than one boolean"),
+                    }
+                }
+                v => bail!("applied a non-function {:?}", v),
+            }
+        }
+    }
+}
+
+fn eval<'a>(e: &'a Expr, env: &mut Env<'a>) -> Result<Value<'a>> {
+    let mut e = e;
+
+    while let Expr::Let(id, _, ref c, ref body) = *e {
+        let v = eval_comp(c, env)?;
+        env.insert(id, v);
+        e = body;
+    }
+
+    match *e {
+        Expr::Comp(ref c) => eval_comp(c, env),
+        Expr::Let(..) => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use slog::{Discard, Logger};
+
+    use super::*;
+    use corpus::{get, PROGRAMS};
+    use eval::Evaluator;
+    use typecheck::TypeError;
+
+    #[test]
+    fn test_pretty() {
+        let (e, _) = convert(&get("((/lam x: #B. (if x 1 2)) (! #T))")).unwrap();
+
+        assert_eq!(e.pretty(),
+                   "let x1: #B = (! #T) in
+((/lam x0: #B.
+  if x0
+    then 1
+    else 2) x1)");
+    }
+
+    #[test]
+    fn test_anf() {
+        let mut ev = Evaluator::new(&Logger::root(Discard, o!()));
+
+        for code in PROGRAMS {
+            let t = get(code);
+            let (e, ty) = convert(&t).unwrap();
+
+            assert_eq!(ty, TypeCheckVisitor::new().type_of(&t).unwrap(), "{}", code);
+            assert_eq!(type_of(&e).unwrap(), ty, "{}", code);
+
+            let v = run(&e).unwrap();
+            match ev.eval(&t).unwrap() {
+                Term::Abs(..) => assert!(v.to_term().is_none(), "{}", code),
+                r => assert_eq!(v.to_term(), Some(r), "{}", code),
+            }
+        }
+
+        let e = convert(&get("(if 1 #T #F)")).unwrap_err();
+        assert_eq!(e.to_string(), "type error: The condition of (If 1 #T #F) has type #Int instead of #B");
+
+        let bad = Expr::Let(0,
+                            TyLit::Bool.into(),
+                            Comp::Atom(Atom::Int(1)),
+                            Box::new(Expr::Comp(Comp::Atom(Atom::Var(0)))));
+        match type_of(&bad).unwrap_err() {
+            TypeError::ArgumentMismatch { position: 1, .. } => {}
+            e => panic!("{:?}", e),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Printing a term with the type of every subterm attached, like hover
+//! information in an editor.
+//!
+//! Each subterm is written `[term : type]`. Types are read off the typing
+//! derivation of the renamed term, which has the same shape as the original,
+//! so variables keep the names they were written with.
+
+use debrujin::RenameVisitor;
+use derivation::Derivation;
+use errors::*;
+use span::{self, Spans};
+use toplevel::Term;
+use typecheck::TypeCheckVisitor;
+
+/// Typechecks `t` and prints it with every subterm annotated.
+pub fn annotate(t: &Term) -> Result<String> {
+    span::check_closed(t, &Spans::new())?;
+    let ast = RenameVisitor::new().rename_term(t)?;
+    let d = TypeCheckVisitor::new().derive(&ast)?;
+
+    Ok(annotated(t, &d))
+}
+
+/// Prints `t` with the types from `d`, a derivation for `t` after renaming.
+pub fn annotated(t: &Term, d: &Derivation) -> String {
+    let child = |i: usize, t: &Term| annotated(t, &d.premises[i]);
+
+    let inner = match *t {
+        Term::Var(ref x) => x.clone(),
+        Term::Int(n) => n.to_string(),
+        Term::True => "#T".into(),
+        Term::False => "#F".into(),
+        Term::Not => "!".into(),
+        Term::Bottom => "_|_".into(),
+        Term::Hole(ref x) => format!("?{}", x),
+        Term::Error => "<error>".into(),
+        Term::Abs(ref args, ref body) => {
+            let args: Vec<String> = args.iter()
+                .map(|&(ref x, ref ty)| format!("{}: {}", x, ty.unparse()))
+                .collect();
+            format!("(/lam {}. {})", args.join(", "), child(0, body))
+        }
+        Term::App(ref f, ref args) => {
+            let args: Vec<String> = args.iter()
+                .enumerate()
+                .map(|(i, a)| child(i + 1, a))
+                .collect();
+            format!("({} {})", child(0, f), args.join(" "))
+        }
+        Term::If(ref c, ref b1, ref b2) => {
+            format!("(if {} {} {})", child(0, c), child(1, b1), child(2, b2))
+        }
+    };
+
+    format!("[{} : {}]", inner, d.show_ty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use corpus::PROGRAMS;
+    use lispy;
+
+    fn parse(s: &str) -> Term {
+        lispy::get_code(s.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_annotate() {
+        for code in PROGRAMS {
+            let s = annotate(&parse(code)).unwrap();
+            assert_eq!(s.matches('[').count(), s.matches(']').count(), "{}", code);
+        }
+
+        assert_eq!(annotate(&parse("((/lam x: #B. (if x 1 2)) #T)")).unwrap(),
+                   "[([(/lam x: #B. [(if [x : #B] [1 : #Int] [2 : #Int]) : #Int]) : (#B -> #Int)] \
+                    [#T : #B]) : #Int]");
+        assert_eq!(annotate(&parse("(/lam f: (#B -> #B), b: #B. (f b))")).unwrap(),
+                   "[(/lam f: (#B -> #B), b: #B. [([f : (#B -> #B)] [b : #B]) : #B]) : \
+                    ((#B -> #B), #B -> #B)]");
+        assert!(annotate(&parse("(! 1)")).is_err());
+        assert!(annotate(&parse("(/lam x: #B. y)")).is_err());
+        assert!(annotate(&parse("(/lam x: #B, x: #B. x)")).is_err());
+    }
+}
@@ -0,0 +1,554 @@
+//! Index-based term representation.
+//!
+//! All nodes of a term live in one `Vec`, children are `Id`s into it and the
+//! argument lists and binder types of every node share two more flat vectors,
+//! so converting a term costs a handful of allocations instead of one per
+//! node. The typechecker and evaluator below work directly on the arena; the
+//! evaluator uses environments rather than substitution, so running a program
+//! never allocates new nodes.
+
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use slog::Logger;
+
+use core::{Term, Ty, TyLit};
+use errors::*;
+use eval::{self, Evaluator};
+use typecheck::{self, Judgement, Partial, TypeCheckVisitor, TypeEnv, TypeError};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Id(u32);
+
+/// A range of `Arena::ids` or `Arena::tys`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Span {
+    start: u32,
+    len: u32,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Node {
+    Var(u32),
+    Abs(Span, Id),
+    App(Id, Span),
+    True,
+    False,
+    Int(i64),
+    Not,
+    If(Id, Id, Id),
+    Stuck,
+    Hole(String),
+}
+
+pub struct Arena {
+    nodes: Vec<Node>,
+    ids: Vec<Id>,
+    tys: Vec<Ty>,
+}
+
+impl Arena {
+    pub fn new() -> Arena {
+        Arena {
+            nodes: Vec::new(),
+            ids: Vec::new(),
+            tys: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn get(&self, id: Id) -> &Node {
+        &self.nodes[id.0 as usize]
+    }
+
+    pub fn ids(&self, span: Span) -> &[Id] {
+        &self.ids[span.start as usize..(span.start + span.len) as usize]
+    }
+
+    pub fn tys(&self, span: Span) -> &[Ty] {
+        &self.tys[span.start as usize..(span.start + span.len) as usize]
+    }
+
+    fn push(&mut self, node: Node) -> Id {
+        self.nodes.push(node);
+        Id(self.nodes.len() as u32 - 1)
+    }
+
+    /// Copies `t` into the arena and returns the id of its root.
+    pub fn alloc(&mut self, t: &Term) -> Id {
+        let node = match *t {
+            Term::Var(k) => Node::Var(k),
+            Term::Abs(ref tys, ref body) => {
+                let span = Span {
+                    start: self.tys.len() as u32,
+                    len: tys.len() as u32,
+                };
+                self.tys.extend(tys.iter().cloned());
+                Node::Abs(span, self.alloc(body))
+            }
+            Term::App(ref f, ref args) => {
+                let f = self.alloc(f);
+                let args: Vec<Id> = args.iter().map(|a| self.alloc(a)).collect();
+                let span = Span {
+                    start: self.ids.len() as u32,
+                    len: args.len() as u32,
+                };
+                self.ids.extend(args);
+                Node::App(f, span)
+            }
+            Term::True => Node::True,
+            Term::False => Node::False,
+            Term::Int(n) => Node::Int(n),
+            Term::Not => Node::Not,
+            Term::If(ref c, ref b1, ref b2) => {
+                Node::If(self.alloc(c), self.alloc(b1), self.alloc(b2))
+            }
+            Term::Stuck => Node::Stuck,
+            Term::Hole(ref x) => Node::Hole(x.clone()),
+        };
+
+        self.push(node)
+    }
+
+    pub fn to_term(&self, id: Id) -> Term {
+        match *self.get(id) {
+            Node::Var(k) => Term::Var(k),
+            Node::Abs(tys, body) => Term::abs(self.tys(tys), self.to_term(body)),
+            Node::App(f, args) => {
+                Term::App(Box::new(self.to_term(f)),
+                          self.ids(args).iter().map(|&a| self.to_term(a)).collect())
+            }
+            Node::True => Term::True,
+            Node::False => Term::False,
+            Node::Int(n) => Term::Int(n),
+            Node::Not => Term::Not,
+            Node::If(c, b1, b2) => Term::if_(self.to_term(c), self.to_term(b1), self.to_term(b2)),
+            Node::Stuck => Term::Stuck,
+            Node::Hole(ref x) => Term::Hole(x.clone()),
+        }
+    }
+
+    /// The names of the holes under `id`, left to right.
+    pub fn holes(&self, id: Id) -> Vec<&str> {
+        match *self.get(id) {
+            Node::Hole(ref x) => vec![x.as_str()],
+            Node::Abs(_, body) => self.holes(body),
+            Node::App(f, args) => {
+                let mut ret = self.holes(f);
+                for &a in self.ids(args) {
+                    ret.extend(self.holes(a));
+                }
+                ret
+            }
+            Node::If(c, b1, b2) => {
+                let mut ret = self.holes(c);
+                ret.extend(self.holes(b1));
+                ret.extend(self.holes(b2));
+                ret
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The same judgement as `TypeCheckVisitor::type_of`.
+    pub fn type_of(&self, id: Id) -> Judgement {
+        match self.check(id, &mut Vec::new(), None)? {
+            Some(ty) => Ok(ty),
+            None => {
+                Err(TypeError::Undetermined {
+                        term: self.to_term(id),
+                        context: TypeEnv::new(),
+                    })
+            }
+        }
+    }
+
+    /// Checks `id` in a position requiring `expected`, which only types holes.
+    fn check(&self, id: Id, gamma: &mut TypeEnv, expected: Option<&Ty>) -> Partial {
+        match *self.get(id) {
+            Node::Var(k) => {
+                gamma.len()
+                    .checked_sub(k as usize)
+                    .and_then(|idx| gamma.get(idx))
+                    .cloned()
+                    .map(Some)
+                    .ok_or_else(|| {
+                        TypeError::UnknownVariable {
+                            var: k,
+                            context: gamma.clone(),
+                        }
+                    })
+            }
+            Node::Abs(tys, body) => {
+                let tys = self.tys(tys);
+                let res = match expected {
+                    Some(&Ty::Arrow(ref params, ref res)) if params[..] == *tys => Some(&**res),
+                    _ => None,
+                };
+
+                gamma.extend(tys.iter().cloned());
+                let ret = self.check(body, gamma, res);
+                let len = gamma.len() - tys.len();
+                gamma.truncate(len);
+                Ok(ret?.map(|res| Ty::arrow(tys, res)))
+            }
+            Node::App(f, args) => {
+                let args = self.ids(args);
+                let (fty, found) = if let Node::Hole(_) = *self.get(f) {
+                    // The arguments first, so the hole can be expected to
+                    // have the function type they determine.
+                    let found = args.iter()
+                        .map(|&a| self.check(a, gamma, None))
+                        .collect::<::std::result::Result<Vec<_>, _>>()?;
+                    let params: Option<Vec<Ty>> = found.iter().cloned().collect();
+                    let fty = match (params, expected) {
+                        (Some(params), Some(res)) => Some(Ty::arrow(&params, res.clone())),
+                        _ => None,
+                    };
+                    (self.check(f, gamma, fty.as_ref())?, found)
+                } else {
+                    let fty = self.check(f, gamma, None)?;
+                    let params = match fty {
+                        Some(Ty::Arrow(ref params, _)) => params.clone(),
+                        _ => Vec::new(),
+                    };
+                    let found = args.iter()
+                        .enumerate()
+                        .map(|(i, &a)| self.check(a, gamma, params.get(i)))
+                        .collect::<::std::result::Result<Vec<_>, _>>()?;
+                    (fty, found)
+                };
+
+                let fty = match fty {
+                    Some(fty) => fty,
+                    None => return Ok(expected.cloned()),
+                };
+
+                match fty {
+                    Ty::Arrow(params, res) => {
+                        if params.len() != found.len() {
+                            return Err(TypeError::ArityMismatch {
+                                           expected: params.len(),
+                                           found: found.len(),
+                                           term: self.to_term(id),
+                                           context: gamma.clone(),
+                                       });
+                        }
+                        for (i, (expected, found)) in params.into_iter().zip(found).enumerate() {
+                            if !typecheck::agrees(&expected, &found) {
+                                return Err(TypeError::ArgumentMismatch {
+                                               position: i + 1,
+                                               expected: expected,
+                                               found: found.unwrap(),
+                                               term: self.to_term(id),
+                                               context: gamma.clone(),
+                                           });
+                            }
+                        }
+                        Ok(Some(*res))
+                    }
+                    fty => {
+                        Err(TypeError::NonFunction {
+                                found: fty,
+                                term: self.to_term(id),
+                                context: gamma.clone(),
+                            })
+                    }
+                }
+            }
+            Node::True | Node::False => Ok(Some(TyLit::Bool.into())),
+            Node::Int(_) => Ok(Some(TyLit::Int.into())),
+            Node::Not => Ok(Some(Ty::arrow(&[TyLit::Bool.into()], TyLit::Bool.into()))),
+            Node::If(c, b1, b2) => {
+                let b = TyLit::Bool.into();
+                let c = self.check(c, gamma, Some(&b))?;
+                let then = self.check(b1, gamma, expected)?;
+                let else_ = self.check(b2, gamma, then.as_ref().or(expected))?;
+
+                if !typecheck::agrees(&b, &c) {
+                    Err(TypeError::NonBoolCondition {
+                            found: c.unwrap(),
+                            term: self.to_term(id),
+                            context: gamma.clone(),
+                        })
+                } else if then.is_some() && !typecheck::agrees(then.as_ref().unwrap(), &else_) {
+                    Err(TypeError::BranchMismatch {
+                            then: then.unwrap(),
+                            else_: else_.unwrap(),
+                            term: self.to_term(id),
+                            context: gamma.clone(),
+                        })
+                } else {
+                    Ok(then.or(else_))
+                }
+            }
+            Node::Stuck => Ok(Some(Ty::Bottom)),
+            Node::Hole(_) => Ok(expected.cloned()),
+        }
+    }
+
+    /// Call-by-value, left-to-right evaluation of a closed term. Programs
+    /// with holes are refused up front, like `Evaluator::eval` does.
+    pub fn eval(&self, id: Id) -> Result<Term> {
+        let holes = self.holes(id);
+        if !holes.is_empty() {
+            bail!("Cannot evaluate a program with unfilled holes: ?{}", holes.join(", ?"));
+        }
+
+        Ok(self.run(id, &Rc::new(Vec::new()))?.readback(self))
+    }
+
+    fn run(&self, id: Id, env: &Env) -> Result<Value> {
+        match *self.get(id) {
+            Node::Var(k) => {
+                env.len()
+                    .checked_sub(k as usize)
+                    .and_then(|idx| env.get(idx))
+                    .cloned()
+                    .ok_or_else(|| format!("unbound variable {}", k).into())
+            }
+            Node::Abs(tys, body) => Ok(Value::Closure(tys, body, env.clone())),
+            Node::App(f, args) => {
+                let f = self.run(f, env)?;
+                let args = self.ids(args)
+                    .iter()
+                    .map(|&a| self.run(a, env))
+                    .collect::<Result<Vec<_>>>()?;
+
+                match f {
+                    Value::Closure(tys, body, ref cenv) if tys.len as usize == args.len() => {
+                        let mut env = cenv.as_ref().clone();
+                        env.extend(args);
+                        self.run(body, &Rc::new(env))
+                    }
+                    Value::Closure(tys, ..) => {
+                        bail!("expected {} argument(s), found {}", tys.len, args.len())
+                    }
+                    Value::Not => {
+                        match (args.len(), args.first()) {
+                            (1, Some(&Value::True)) => Ok(Value::False),
+                            (1, Some(&Value::False)) => Ok(Value::True),
+                            _ => bail!("negated something other than one boolean"),
+                        }
+                    }
+                    f => bail!("applied a non-function {}", f.readback(self).unparse()),
+                }
+            }
+            Node::True => Ok(Value::True),
+            Node::False => Ok(Value::False),
+            Node::Int(n) => Ok(Value::Int(n)),
+            Node::Not => Ok(Value::Not),
+            Node::If(c, b1, b2) => {
+                match self.run(c, env)? {
+                    Value::True => self.run(b1, env),
+                    Value::False => self.run(b2, env),
+                    c => bail!("if condition {} is not a boolean", c.readback(self).unparse()),
+                }
+            }
+            Node::Stuck => bail!("reached bottom"),
+            Node::Hole(ref x) => bail!("reached hole ?{}", x),
+        }
+    }
+}
+
+/// Innermost binding last, so `Var(k)` is `env[env.len() - k]`.
+type Env = Rc<Vec<Value>>;
+
+#[derive(Clone, Debug)]
+enum Value {
+    True,
+    False,
+    Int(i64),
+    Not,
+    Closure(Span, Id, Env),
+}
+
+impl Value {
+    fn readback(&self, arena: &Arena) -> Term {
+        match *self {
+            Value::True => Term::True,
+            Value::False => Term::False,
+            Value::Int(n) => Term::Int(n),
+            Value::Not => Term::Not,
+            Value::Closure(tys, body, ref env) => {
+                let env: Vec<Term> = env.iter().map(|v| v.readback(arena)).collect();
+                eval::beta(&Term::abs(arena.tys(tys), arena.to_term(body)), &env)
+            }
+        }
+    }
+}
+
+/// A closed boolean term with `2^depth` leaves, built from nested
+/// applications of a two-argument lambda.
+pub fn generate(depth: u32) -> Term {
+    fn go(depth: u32, leaf: bool) -> Term {
+        if depth == 0 {
+            return if leaf { Term::True } else { Term::False };
+        }
+
+        let b: Ty = TyLit::Bool.into();
+        // (/lam a: #B, b: #B. (if a b (! b)))
+        let f = Term::abs(&[b.clone(), b],
+                          Term::if_(Term::Var(2),
+                                    Term::Var(1),
+                                    Term::App(Box::new(Term::Not), vec![Term::Var(1)])));
+        Term::App(Box::new(f), vec![go(depth - 1, leaf), go(depth - 1, !leaf)])
+    }
+
+    go(depth, true)
+}
+
+pub struct Bench {
+    pub runs: u32,
+    pub nodes: usize,
+    pub boxed_check: Duration,
+    pub arena_check: Duration,
+    pub boxed_eval: Duration,
+    pub arena_eval: Duration,
+    /// Time spent copying the term into the arena, once.
+    pub alloc: Duration,
+}
+
+impl fmt::Display for Bench {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let micros = |d: Duration| d.as_secs() * 1_000_000 + d.subsec_nanos() as u64 / 1000;
+
+        write!(f,
+               "{} nodes, {} runs: typecheck boxed {}us, arena {}us; eval boxed {}us, arena {}us; \
+                arena conversion {}us",
+               self.nodes,
+               self.runs,
+               micros(self.boxed_check),
+               micros(self.arena_check),
+               micros(self.boxed_eval),
+               micros(self.arena_eval),
+               micros(self.alloc))
+    }
+}
+
+pub fn bench(t: &Term, runs: u32, logger: &Logger) -> Result<Bench> {
+    let start = Instant::now();
+    for _ in 0..runs {
+        TypeCheckVisitor::new().type_of(t)?;
+    }
+    let boxed_check = start.elapsed();
+
+    let mut evaluator = Evaluator::new(logger);
+    let start = Instant::now();
+    for _ in 0..runs {
+        evaluator.eval(t)?;
+    }
+    let boxed_eval = start.elapsed();
+
+    let start = Instant::now();
+    let mut arena = Arena::new();
+    let root = arena.alloc(t);
+    let alloc = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..runs {
+        arena.type_of(root)?;
+    }
+    let arena_check = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..runs {
+        arena.eval(root)?;
+    }
+    let arena_eval = start.elapsed();
+
+    Ok(Bench {
+           runs: runs,
+           nodes: arena.len(),
+           boxed_check: boxed_check,
+           arena_check: arena_check,
+           boxed_eval: boxed_eval,
+           arena_eval: arena_eval,
+           alloc: alloc,
+       })
+}
+
+#[cfg(test)]
+mod test {
+    use slog::{Discard, Logger};
+
+    use super::*;
+    use corpus::{get, PROGRAMS};
+
+    #[test]
+    fn test_roundtrip() {
+        let mut arena = Arena::new();
+
+        for code in PROGRAMS {
+            let t = get(code);
+            let id = arena.alloc(&t);
+            assert_eq!(arena.to_term(id), t);
+        }
+    }
+
+    #[test]
+    fn test_agrees() {
+        let mut ev = Evaluator::new(&Logger::root(Discard, o!()));
+        let mut arena = Arena::new();
+
+        for code in PROGRAMS {
+            let t = get(code);
+            let id = arena.alloc(&t);
+
+            assert_eq!(arena.type_of(id).unwrap(),
+                       TypeCheckVisitor::new().type_of(&t).unwrap(),
+                       "{}",
+                       code);
+            assert_eq!(arena.eval(id).unwrap(), ev.eval(&t).unwrap(), "{}", code);
+        }
+
+        for code in &["(if 1 #T #F)", "((/lam x: #B. x) 1)", "(1 2)"] {
+            let id = arena.alloc(&get(code));
+            assert_eq!(arena.type_of(id).unwrap_err(),
+                       TypeCheckVisitor::new().type_of(&get(code)).unwrap_err(),
+                       "{}",
+                       code);
+        }
+        let id = arena.alloc(&Term::if_(Term::True, Term::Stuck, Term::Int(1)));
+        assert!(arena.eval(id).is_err());
+    }
+
+    #[test]
+    fn test_holes() {
+        let mut arena = Arena::new();
+
+        for code in &["(/lam x: #Int, y: #B. (if ?c (! ?b) #F))",
+                      "(/lam n: #Int. (if (?f n) 1 2))",
+                      "(if #T 1 ?x)",
+                      "(/lam x: #B. ((/lam y: #B. ?h) #T))",
+                      "(if ?c 1 #F)"] {
+            let t = get(code);
+            let id = arena.alloc(&t);
+
+            assert_eq!(arena.to_term(id), t);
+            assert_eq!(arena.type_of(id), TypeCheckVisitor::new().type_of(&t), "{}", code);
+        }
+
+        let id = arena.alloc(&get("(if ?c ?a #T)"));
+        assert_eq!(arena.eval(id).unwrap_err().to_string(),
+                   "Cannot evaluate a program with unfilled holes: ?c, ?a");
+    }
+
+    #[test]
+    fn test_generate() {
+        let t = generate(6);
+        let mut arena = Arena::new();
+        let id = arena.alloc(&t);
+
+        assert_eq!(arena.type_of(id).unwrap(), TyLit::Bool.into());
+        assert_eq!(arena.eval(id).unwrap(),
+                   Evaluator::new(&Logger::root(Discard, o!())).eval(&t).unwrap());
+
+        let b = bench(&generate(3), 2, &Logger::root(Discard, o!())).unwrap();
+        assert_eq!(b.nodes, 64);
+    }
+}
@@ -0,0 +1,211 @@
+use core::Term;
+use closure::{self, Expr, Program};
+use errors::*;
+
+const RUNTIME: &'static str = r##"#include <stdio.h>
+#include <stdlib.h>
+
+typedef struct value value;
+typedef value *(*code)(value **args, value **env);
+
+enum tag { TAG_INT, TAG_BOOL, TAG_NOT, TAG_CLOSURE };
+
+struct value {
+    enum tag tag;
+    long long i;
+    code fn;
+    int arity;
+    value **env;
+};
+
+static value sf_true = { TAG_BOOL, 1, 0, 0, 0 };
+static value sf_false = { TAG_BOOL, 0, 0, 0, 0 };
+static value sf_not = { TAG_NOT, 0, 0, 1, 0 };
+
+static value *sf_stuck(const char *why) {
+    fprintf(stderr, "stuck: %s\n", why);
+    exit(1);
+    return 0;
+}
+
+static value *sf_alloc(enum tag tag) {
+    value *v = malloc(sizeof(value));
+    if (!v) sf_stuck("out of memory");
+    v->tag = tag;
+    return v;
+}
+
+static value *sf_int(long long i) {
+    value *v = sf_alloc(TAG_INT);
+    v->i = i;
+    return v;
+}
+
+static value *sf_bool(int b) {
+    return b ? &sf_true : &sf_false;
+}
+
+static value *sf_closure(code fn, int arity, int n, value **captured) {
+    int k;
+    value *v = sf_alloc(TAG_CLOSURE);
+    v->fn = fn;
+    v->arity = arity;
+    v->env = malloc(sizeof(value *) * (n ? n : 1));
+    if (!v->env) sf_stuck("out of memory");
+    for (k = 0; k < n; k++) v->env[k] = captured[k];
+    return v;
+}
+
+static int sf_truth(value *v) {
+    if (v->tag != TAG_BOOL) sf_stuck("if condition is not a boolean");
+    return (int)v->i;
+}
+
+static value *sf_apply(value *f, int n, value **args) {
+    switch (f->tag) {
+    case TAG_NOT:
+        if (n != 1) sf_stuck("arity mismatch");
+        return sf_bool(!sf_truth(args[0]));
+    case TAG_CLOSURE:
+        if (n != f->arity) sf_stuck("arity mismatch");
+        return f->fn(args, f->env);
+    default:
+        return sf_stuck("applied a non-function");
+    }
+}
+
+static void sf_print(value *v) {
+    switch (v->tag) {
+    case TAG_INT: printf("%lld\n", v->i); break;
+    case TAG_BOOL: printf("%s\n", v->i ? "#T" : "#F"); break;
+    case TAG_NOT: printf("!\n"); break;
+    case TAG_CLOSURE: printf("<closure>\n"); break;
+    }
+}
+"##;
+
+fn expr(e: &Expr, program: &Program) -> String {
+    let array = |es: &[Expr]| -> String {
+        if es.is_empty() {
+            "0".into()
+        } else {
+            let es: Vec<String> = es.iter().map(|e| expr(e, program)).collect();
+            format!("(value *[]){{{}}}", es.join(", "))
+        }
+    };
+
+    match *e {
+        // `-9223372036854775808` is not a valid C literal, so build it from a negation.
+        Expr::Int(n) if n == i64::min_value() => format!("sf_int({}LL - 1)", n + 1),
+        Expr::Int(n) => format!("sf_int({}LL)", n),
+        Expr::Bool(true) => "&sf_true".into(),
+        Expr::Bool(false) => "&sf_false".into(),
+        Expr::Not => "&sf_not".into(),
+        Expr::Param(i) => format!("args[{}]", i),
+        Expr::Env(i) => format!("env[{}]", i),
+        Expr::Stuck => "sf_stuck(\"reached bottom\")".into(),
+        Expr::MakeClosure(f, ref captured) => {
+            format!("sf_closure(fn{}, {}, {}, {})",
+                    f,
+                    program.functions[f].arity,
+                    captured.len(),
+                    array(captured))
+        }
+        Expr::Call(ref f, ref args) => {
+            format!("sf_apply({}, {}, {})",
+                    expr(f, program),
+                    args.len(),
+                    array(args))
+        }
+        Expr::If(ref c, ref b1, ref b2) => {
+            format!("(sf_truth({}) ? {} : {})",
+                    expr(c, program),
+                    expr(b1, program),
+                    expr(b2, program))
+        }
+    }
+}
+
+/// Compiles a closed, typechecked term to a standalone C program that prints its value.
+pub fn emit(t: &Term) -> Result<String> {
+    let program = closure::convert(t)?;
+    let mut out = String::from(RUNTIME);
+
+    out.push('\n');
+    for i in 0..program.functions.len() {
+        out.push_str(&format!("static value *fn{}(value **args, value **env);\n", i));
+    }
+
+    for (i, f) in program.functions.iter().enumerate() {
+        out.push_str(&format!("\nstatic value *fn{}(value **args, value **env) {{\n", i));
+        out.push_str("    (void)args;\n    (void)env;\n");
+        out.push_str(&format!("    return {};\n}}\n", expr(&f.body, &program)));
+    }
+
+    out.push_str("\nint main(void) {\n");
+    out.push_str(&format!("    sf_print({});\n", expr(&program.main, &program)));
+    out.push_str("    return 0;\n}\n");
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::process::{self, Command};
+
+    use slog::{Discard, Logger};
+
+    use super::emit;
+    use core::Term;
+    use corpus::{get, PROGRAMS};
+    use eval::Evaluator;
+
+    fn expected(v: &Term) -> String {
+        match *v {
+            Term::Int(_) | Term::True | Term::False | Term::Not => v.unparse(),
+            _ => "<closure>".into(),
+        }
+    }
+
+    #[test]
+    fn test_cc() {
+        if Command::new("cc").arg("--version").output().is_err() {
+            println!("no C compiler found, skipping");
+            return;
+        }
+
+        let mut ev = Evaluator::new(&Logger::root(Discard, o!()));
+        let dir = env::temp_dir();
+
+        for (i, code) in PROGRAMS.iter().enumerate() {
+            let t = get(code);
+            let name = format!("system-f-c-{}-{}", process::id(), i);
+            let src = dir.join(format!("{}.c", name));
+            let exe = dir.join(&name);
+
+            File::create(&src).unwrap().write_all(emit(&t).unwrap().as_bytes()).unwrap();
+
+            let status = Command::new("cc")
+                .arg("-std=c99")
+                .arg("-Wall")
+                .arg("-o")
+                .arg(&exe)
+                .arg(&src)
+                .status()
+                .unwrap();
+            fs::remove_file(&src).unwrap();
+            assert!(status.success(), "{}", code);
+
+            let out = Command::new(&exe).output().unwrap();
+            fs::remove_file(&exe).unwrap();
+
+            assert_eq!(String::from_utf8(out.stdout).unwrap().trim(),
+                       expected(&ev.eval(&t).unwrap()),
+                       "{}",
+                       code);
+        }
+    }
+}
@@ -0,0 +1,314 @@
+//! WebAssembly text format backend.
+//!
+//! Every value is an `i32` pointer into linear memory to a cell whose first
+//! word is a tag:
+//!
+//! * int: `[0, _, i64]`
+//! * bool: `[1, 0 or 1]`
+//! * `!`: `[2]`
+//! * closure: `[3, table index, arity, env size, env...]`
+//!
+//! `#F`, `#T` and `!` live in a static data segment; everything else is bump
+//! allocated and never freed. Functions take a pointer to their argument
+//! array and to their closure environment.
+
+use core::Term;
+use closure::{self, Expr, Program};
+use errors::*;
+
+const FALSE: u32 = 0;
+const TRUE: u32 = 8;
+const NOT: u32 = 16;
+const HEAP_START: u32 = 24;
+const CLOSURE_ENV: u32 = 16;
+
+const RUNTIME: &'static str = r#"  (type $code (func (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "\01\00\00\00\00\00\00\00\01\00\00\00\01\00\00\00\02\00\00\00\00\00\00\00")
+  (global $hp (mut i32) (i32.const HEAP_START))
+
+  (func $alloc (param $size i32) (result i32)
+    (local $p i32)
+    (local.set $p (global.get $hp))
+    (global.set $hp (i32.and (i32.add (i32.add (local.get $p) (local.get $size)) (i32.const 7))
+                             (i32.const -8)))
+    (if (i32.gt_u (global.get $hp) (i32.mul (memory.size) (i32.const 65536)))
+      (then (drop (memory.grow (i32.add (i32.shr_u (local.get $size) (i32.const 16))
+                                        (i32.const 1))))))
+    (local.get $p))
+
+  (func $int (param $n i64) (result i32)
+    (local $p i32)
+    (local.set $p (call $alloc (i32.const 16)))
+    (i32.store (local.get $p) (i32.const 0))
+    (i64.store offset=8 (local.get $p) (local.get $n))
+    (local.get $p))
+
+  (func $bool (param $b i32) (result i32)
+    (select (i32.const TRUE) (i32.const FALSE) (local.get $b)))
+
+  (func $truth (param $v i32) (result i32)
+    (if (i32.ne (i32.load (local.get $v)) (i32.const 1)) (then (unreachable)))
+    (i32.load offset=4 (local.get $v)))
+
+  (func $closure (param $f i32) (param $arity i32) (param $n i32) (result i32)
+    (local $p i32)
+    (local.set $p (call $alloc (i32.add (i32.const CLOSURE_ENV) (i32.shl (local.get $n) (i32.const 2)))))
+    (i32.store (local.get $p) (i32.const 3))
+    (i32.store offset=4 (local.get $p) (local.get $f))
+    (i32.store offset=8 (local.get $p) (local.get $arity))
+    (i32.store offset=12 (local.get $p) (local.get $n))
+    (local.get $p))
+
+  (func $apply (param $f i32) (param $args i32) (param $n i32) (result i32)
+    (if (i32.eq (i32.load (local.get $f)) (i32.const 2))
+      (then
+        (if (i32.ne (local.get $n) (i32.const 1)) (then (unreachable)))
+        (return (call $bool (i32.eqz (call $truth (i32.load (local.get $args))))))))
+    (if (i32.ne (i32.load (local.get $f)) (i32.const 3)) (then (unreachable)))
+    (if (i32.ne (i32.load offset=8 (local.get $f)) (local.get $n)) (then (unreachable)))
+    (call_indirect (type $code)
+      (local.get $args)
+      (i32.add (local.get $f) (i32.const CLOSURE_ENV))
+      (i32.load offset=4 (local.get $f))))
+
+  (func (export "tag") (param $v i32) (result i32)
+    (i32.load (local.get $v)))
+
+  (func (export "int_value") (param $v i32) (result i64)
+    (i64.load offset=8 (local.get $v)))
+
+  (func (export "bool_value") (param $v i32) (result i32)
+    (call $truth (local.get $v)))
+"#;
+
+/// Emits the body of one function, allocating `i32` locals as it goes.
+struct FnEmitter<'a> {
+    program: &'a Program,
+    temps: usize,
+}
+
+impl<'a> FnEmitter<'a> {
+    fn temp(&mut self) -> String {
+        self.temps += 1;
+        format!("$t{}", self.temps - 1)
+    }
+
+    /// Evaluates `alloc` into a new local and stores `es` from offset `base` of it.
+    fn build(&mut self, alloc: String, base: u32, es: &[Expr]) -> (String, String) {
+        let t = self.temp();
+        let mut out = format!("(local.set {} {})", t, alloc);
+        for (i, e) in es.iter().enumerate() {
+            out.push_str(&format!(" (i32.store offset={} (local.get {}) {})",
+                                  base + 4 * i as u32,
+                                  t,
+                                  self.expr(e)));
+        }
+        (out, t)
+    }
+
+    fn expr(&mut self, e: &Expr) -> String {
+        match *e {
+            Expr::Int(n) => format!("(call $int (i64.const {}))", n),
+            Expr::Bool(true) => format!("(i32.const {})", TRUE),
+            Expr::Bool(false) => format!("(i32.const {})", FALSE),
+            Expr::Not => format!("(i32.const {})", NOT),
+            Expr::Param(i) => format!("(i32.load offset={} (local.get $args))", 4 * i),
+            Expr::Env(i) => format!("(i32.load offset={} (local.get $env))", 4 * i),
+            Expr::Stuck => "(unreachable)".into(),
+            Expr::MakeClosure(f, ref captured) => {
+                let alloc = format!("(call $closure (i32.const {}) (i32.const {}) (i32.const {}))",
+                                    f,
+                                    self.program.functions[f].arity,
+                                    captured.len());
+                let (stores, t) = self.build(alloc, CLOSURE_ENV, captured);
+                format!("(block (result i32) {} (local.get {}))", stores, t)
+            }
+            Expr::Call(ref f, ref args) => {
+                let f = self.expr(f);
+                let alloc = format!("(call $alloc (i32.const {}))", 4 * args.len());
+                let (stores, t) = self.build(alloc, 0, args);
+                format!("(block (result i32) {} (call $apply {} (local.get {}) (i32.const {})))",
+                        stores,
+                        f,
+                        t,
+                        args.len())
+            }
+            Expr::If(ref c, ref b1, ref b2) => {
+                format!("(if (result i32) (call $truth {}) (then {}) (else {}))",
+                        self.expr(c),
+                        self.expr(b1),
+                        self.expr(b2))
+            }
+        }
+    }
+
+    fn locals(&self) -> String {
+        (0..self.temps).map(|i| format!(" (local $t{} i32)", i)).collect()
+    }
+}
+
+/// Compiles a closed, typechecked term to a WAT module exporting `main`.
+pub fn emit(t: &Term) -> Result<String> {
+    let program = closure::convert(t)?;
+    let runtime = RUNTIME.replace("HEAP_START", &HEAP_START.to_string())
+        .replace("CLOSURE_ENV", &CLOSURE_ENV.to_string())
+        .replace("TRUE", &TRUE.to_string())
+        .replace("FALSE", &FALSE.to_string());
+
+    let mut out = String::from("(module\n");
+    out.push_str(&runtime);
+
+    // `$apply` uses `call_indirect`, so the table must exist even when empty.
+    let names: Vec<String> = (0..program.functions.len()).map(|i| format!("$fn{}", i)).collect();
+    out.push_str(&format!("\n  (table {} funcref)\n", names.len()));
+    if !names.is_empty() {
+        out.push_str(&format!("  (elem (i32.const 0) {})\n", names.join(" ")));
+    }
+
+    for (i, f) in program.functions.iter().enumerate() {
+        let mut e = FnEmitter {
+            program: &program,
+            temps: 0,
+        };
+        let body = e.expr(&f.body);
+        out.push_str(&format!("\n  (func $fn{} (type $code) (param $args i32) (param $env i32) \
+                               (result i32){}\n    {})\n",
+                              i,
+                              e.locals(),
+                              body));
+    }
+
+    let mut e = FnEmitter {
+        program: &program,
+        temps: 0,
+    };
+    let body = e.expr(&program.main);
+    out.push_str(&format!("\n  (func (export \"main\") (result i32){}\n    {})\n)\n",
+                          e.locals(),
+                          body));
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::emit;
+    use closure;
+    use corpus::{get, PROGRAMS};
+
+    #[derive(Debug)]
+    enum Sexp {
+        Atom(String),
+        List(Vec<Sexp>),
+    }
+
+    fn parse(src: &str) -> Sexp {
+        let mut stack = vec![Vec::new()];
+        let mut atom = String::new();
+        let mut in_str = false;
+
+        for c in src.chars() {
+            if in_str {
+                atom.push(c);
+                in_str = c != '"';
+                continue;
+            }
+            if c == '(' || c == ')' || c.is_whitespace() {
+                if !atom.is_empty() {
+                    stack.last_mut().unwrap().push(Sexp::Atom(atom.clone()));
+                    atom.clear();
+                }
+            }
+            match c {
+                '(' => stack.push(Vec::new()),
+                ')' => {
+                    let list = stack.pop().unwrap();
+                    stack.last_mut().expect("unbalanced )").push(Sexp::List(list));
+                }
+                '"' => {
+                    atom.push(c);
+                    in_str = true;
+                }
+                c if c.is_whitespace() => {}
+                c => atom.push(c),
+            }
+        }
+
+        assert_eq!(stack.len(), 1, "unbalanced (");
+        let mut top = stack.pop().unwrap();
+        assert_eq!(top.len(), 1);
+        top.pop().unwrap()
+    }
+
+    fn head(s: &Sexp) -> Option<&str> {
+        match *s {
+            Sexp::List(ref l) => {
+                match l.first() {
+                    Some(&Sexp::Atom(ref a)) => Some(a),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks every `local.get` names a parameter or declared local of its function.
+    fn check_locals(s: &Sexp, declared: &HashSet<String>) {
+        if let Sexp::List(ref l) = *s {
+            if head(s) == Some("local.get") || head(s) == Some("local.set") {
+                match l.get(1) {
+                    Some(&Sexp::Atom(ref name)) => assert!(declared.contains(name), "{}", name),
+                    _ => panic!("malformed {:?}", s),
+                }
+            }
+            for x in l {
+                check_locals(x, declared);
+            }
+        }
+    }
+
+    #[test]
+    fn test_wat_structure() {
+        for code in PROGRAMS {
+            let t = get(code);
+            let wat = emit(&t).unwrap();
+            let module = parse(&wat);
+            assert_eq!(head(&module), Some("module"));
+
+            let items = match module {
+                Sexp::List(l) => l,
+                _ => unreachable!(),
+            };
+
+            let funcs: Vec<&Sexp> = items.iter().filter(|i| head(i) == Some("func")).collect();
+            let lifted = closure::convert(&t).unwrap().functions.len();
+            // 9 runtime functions, the lifted functions and main
+            assert_eq!(funcs.len(), 9 + lifted + 1, "{}", code);
+
+            for f in funcs {
+                let mut declared = HashSet::new();
+                if let Sexp::List(ref l) = *f {
+                    for x in l {
+                        if head(x) == Some("param") || head(x) == Some("local") {
+                            if let Sexp::List(ref p) = *x {
+                                if let Some(&Sexp::Atom(ref name)) = p.get(1) {
+                                    declared.insert(name.clone());
+                                }
+                            }
+                        }
+                    }
+                }
+                check_locals(f, &declared);
+            }
+
+            assert_eq!(items.iter().filter(|i| head(i) == Some("table")).count(), 1);
+            let elems = items.iter().filter(|i| head(i) == Some("elem")).count();
+            assert_eq!(elems, if lifted > 0 { 1 } else { 0 });
+            assert!(wat.contains("(export \"main\")"));
+            assert!(wat.contains("(export \"memory\")"));
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use core::Term;
+use bytecode::{Function, Instr, Program};
+use errors::*;
+
+struct Compiler {
+    functions: Vec<Function>,
+}
+
+impl Compiler {
+    fn function(&mut self, arity: usize, body: &Term, source: Term) -> Result<usize> {
+        let idx = self.functions.len();
+        self.functions.push(Function {
+                                arity: arity,
+                                code: Vec::new(),
+                                source: source,
+                            });
+
+        let mut code = Vec::new();
+        self.term(body, &mut code)?;
+        code.push(Instr::Ret);
+
+        self.functions[idx].code = code;
+        Ok(idx)
+    }
+
+    fn term(&mut self, t: &Term, code: &mut Vec<Instr>) -> Result<()> {
+        match *t {
+            Term::Int(n) => code.push(Instr::Int(n)),
+            Term::True => code.push(Instr::True),
+            Term::False => code.push(Instr::False),
+            Term::Not => code.push(Instr::NotFn),
+            Term::Var(k) => code.push(Instr::Var(k)),
+            Term::Stuck => code.push(Instr::Stuck),
+            Term::Hole(ref x) => bail!("Cannot compile the unfilled hole ?{}", x),
+            Term::Abs(ref tys, ref body) => {
+                let idx = self.function(tys.len(), body, t.clone())?;
+                code.push(Instr::Closure(idx));
+            }
+            Term::App(ref f, ref args) if **f == Term::Not && args.len() == 1 => {
+                self.term(&args[0], code)?;
+                code.push(Instr::Not);
+            }
+            Term::App(ref f, ref args) => {
+                self.term(f, code)?;
+                for arg in args {
+                    self.term(arg, code)?;
+                }
+                code.push(Instr::Call(args.len()));
+            }
+            Term::If(ref cond, ref b1, ref b2) => {
+                self.term(cond, code)?;
+                let jump_else = code.len();
+                code.push(Instr::JumpIfFalse(0));
+
+                self.term(b1, code)?;
+                let jump_end = code.len();
+                code.push(Instr::Jump(0));
+
+                code[jump_else] = Instr::JumpIfFalse(code.len());
+                self.term(b2, code)?;
+                code[jump_end] = Instr::Jump(code.len());
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn compile(t: &Term) -> Result<Program> {
+    let mut c = Compiler { functions: Vec::new() };
+    c.function(0, t, Term::abs(&[], t.clone()))?;
+
+    Ok(Program { functions: c.functions })
+}
+
+#[cfg(test)]
+mod test {
+    use super::compile;
+    use corpus::get;
+
+    #[test]
+    fn test_disassemble() {
+        let p = compile(&get("((/lam x: #B. (if x 1 2)) (! #T))")).unwrap();
+
+        assert_eq!(p.disassemble(),
+                   "fn0 (arity 0):
+  0000  closure fn1
+  0001  true
+  0002  not
+  0003  call 1
+  0004  ret
+fn1 (arity 1):
+  0000  var 1
+  0001  jumpf 0004
+  0002  int 1
+  0003  jump 0005
+  0004  int 2
+  0005  ret
+");
+    }
+}
@@ -0,0 +1,125 @@
+pub mod compile;
+pub mod vm;
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use slog::Logger;
+
+use core::Term;
+use errors::*;
+use eval::Evaluator;
+
+pub use self::compile::compile;
+pub use self::vm::Vm;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Instr {
+    Int(i64),
+    True,
+    False,
+    /// Pushes the value of a de Bruijn variable from the current environment.
+    Var(u32),
+    /// Pushes a closure over the current environment for the given function.
+    Closure(usize),
+    /// Pushes `!` as a first-class function.
+    NotFn,
+    /// Negates the boolean on top of the stack.
+    Not,
+    /// Applies the function below the given number of arguments.
+    Call(usize),
+    Ret,
+    Jump(usize),
+    JumpIfFalse(usize),
+    /// Reached `_|_`.
+    Stuck,
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instr::Int(n) => write!(f, "int {}", n),
+            Instr::True => write!(f, "true"),
+            Instr::False => write!(f, "false"),
+            Instr::Var(k) => write!(f, "var {}", k),
+            Instr::Closure(func) => write!(f, "closure fn{}", func),
+            Instr::NotFn => write!(f, "notfn"),
+            Instr::Not => write!(f, "not"),
+            Instr::Call(n) => write!(f, "call {}", n),
+            Instr::Ret => write!(f, "ret"),
+            Instr::Jump(pc) => write!(f, "jump {:04}", pc),
+            Instr::JumpIfFalse(pc) => write!(f, "jumpf {:04}", pc),
+            Instr::Stuck => write!(f, "stuck"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Function {
+    pub arity: usize,
+    pub code: Vec<Instr>,
+    /// The abstraction this function was compiled from, used to read closures back.
+    pub source: Term,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Program {
+    /// Function 0 is the entry point and takes no arguments.
+    pub functions: Vec<Function>,
+}
+
+impl Program {
+    pub fn disassemble(&self) -> String {
+        let mut out = String::new();
+
+        for (i, func) in self.functions.iter().enumerate() {
+            out.push_str(&format!("fn{} (arity {}):\n", i, func.arity));
+            for (pc, instr) in func.code.iter().enumerate() {
+                out.push_str(&format!("  {:04}  {}\n", pc, instr));
+            }
+        }
+
+        out
+    }
+}
+
+/// Timings of running the same term with both evaluators.
+pub struct Bench {
+    pub runs: u32,
+    pub tree_walk: Duration,
+    pub vm: Duration,
+}
+
+impl fmt::Display for Bench {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let micros = |d: Duration| d.as_secs() * 1_000_000 + d.subsec_nanos() as u64 / 1000;
+
+        write!(f,
+               "{} runs: tree-walking {}us, vm {}us",
+               self.runs,
+               micros(self.tree_walk),
+               micros(self.vm))
+    }
+}
+
+pub fn bench(t: &Term, runs: u32, logger: &Logger) -> Result<Bench> {
+    let mut evaluator = Evaluator::new(logger);
+    let start = Instant::now();
+    for _ in 0..runs {
+        evaluator.eval(t)?;
+    }
+    let tree_walk = start.elapsed();
+
+    let program = compile(t)?;
+    let start = Instant::now();
+    for _ in 0..runs {
+        Vm::new(&program).run()?;
+    }
+    let vm = start.elapsed();
+
+    Ok(Bench {
+           runs: runs,
+           tree_walk: tree_walk,
+           vm: vm,
+       })
+}
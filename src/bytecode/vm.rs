@@ -0,0 +1,182 @@
+use std::rc::Rc;
+
+use core::Term;
+use errors::*;
+use eval;
+use bytecode::{Instr, Program};
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Not,
+    /// A function index and the environment it closes over, innermost binding last.
+    Closure(usize, Rc<Vec<Value>>),
+}
+
+struct Frame {
+    func: usize,
+    pc: usize,
+    env: Rc<Vec<Value>>,
+}
+
+pub struct Vm<'a> {
+    program: &'a Program,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(program: &'a Program) -> Vm<'a> {
+        Vm {
+            program: program,
+            stack: Vec::new(),
+            frames: vec![Frame {
+                             func: 0,
+                             pc: 0,
+                             env: Rc::new(Vec::new()),
+                         }],
+        }
+    }
+
+    pub fn run(&mut self) -> Result<Term> {
+        let v = self.execute()?;
+        Ok(self.readback(&v))
+    }
+
+    /// Converts a value back into a closed term.
+    pub fn readback(&self, v: &Value) -> Term {
+        match *v {
+            Value::Bool(true) => Term::True,
+            Value::Bool(false) => Term::False,
+            Value::Int(n) => Term::Int(n),
+            Value::Not => Term::Not,
+            Value::Closure(func, ref env) => {
+                let env: Vec<Term> = env.iter().map(|v| self.readback(v)).collect();
+                eval::beta(&self.program.functions[func].source, &env)
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Result<Value> {
+        self.stack.pop().ok_or("VM stack underflow".into())
+    }
+
+    fn execute(&mut self) -> Result<Value> {
+        loop {
+            let instr = {
+                let frame = self.frames.last_mut().ok_or("VM has no frame")?;
+                let instr = self.program.functions[frame.func].code[frame.pc];
+                frame.pc += 1;
+                instr
+            };
+
+            match instr {
+                Instr::Int(n) => self.stack.push(Value::Int(n)),
+                Instr::True => self.stack.push(Value::Bool(true)),
+                Instr::False => self.stack.push(Value::Bool(false)),
+                Instr::NotFn => self.stack.push(Value::Not),
+                Instr::Var(k) => {
+                    let v = {
+                        let env = &self.frames.last().unwrap().env;
+                        env.len()
+                            .checked_sub(k as usize)
+                            .and_then(|i| env.get(i))
+                            .cloned()
+                            .ok_or(format!("VM found unbound variable {}", k))?
+                    };
+                    self.stack.push(v);
+                }
+                Instr::Closure(func) => {
+                    let env = self.frames.last().unwrap().env.clone();
+                    self.stack.push(Value::Closure(func, env));
+                }
+                Instr::Not => {
+                    match self.pop()? {
+                        Value::Bool(b) => self.stack.push(Value::Bool(!b)),
+                        v => bail!("VM negated a non-boolean {:?}", v),
+                    }
+                }
+                Instr::Call(n) => {
+                    let at = self.stack.len().checked_sub(n + 1).ok_or("VM stack underflow")?;
+                    let args = self.stack.split_off(at + 1);
+                    let f = self.pop()?;
+                    self.call(f, args)?;
+                }
+                Instr::Ret => {
+                    self.frames.pop();
+                    if self.frames.is_empty() {
+                        return self.pop();
+                    }
+                }
+                Instr::Jump(pc) => self.frames.last_mut().unwrap().pc = pc,
+                Instr::JumpIfFalse(pc) => {
+                    match self.pop()? {
+                        Value::Bool(true) => {}
+                        Value::Bool(false) => self.frames.last_mut().unwrap().pc = pc,
+                        v => bail!("VM branched on a non-boolean {:?}", v),
+                    }
+                }
+                Instr::Stuck => bail!("VM reached bottom"),
+            }
+        }
+    }
+
+    fn call(&mut self, f: Value, args: Vec<Value>) -> Result<()> {
+        match f {
+            Value::Closure(func, env) => {
+                let arity = self.program.functions[func].arity;
+                if arity != args.len() {
+                    bail!("VM expected {} argument(s), found {}", arity, args.len());
+                }
+
+                let mut env = env.as_ref().clone();
+                env.extend(args);
+                self.frames.push(Frame {
+                                     func: func,
+                                     pc: 0,
+                                     env: Rc::new(env),
+                                 });
+            }
+            Value::Not => {
+                match (args.len(), args.first()) {
+                    (1, Some(&Value::Bool(b))) => self.stack.push(Value::Bool(!b)),
+                    _ => bail!("VM negated something other than one boolean"),
+                }
+            }
+            v => bail!("VM applied a non-function {:?}", v),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use slog::{Discard, Logger};
+
+    use bytecode::{bench, compile, Vm};
+    use corpus::{get, PROGRAMS};
+    use eval::Evaluator;
+
+    #[test]
+    fn test_vm() {
+        let mut ev = Evaluator::new(&Logger::root(Discard, o!()));
+
+        for code in PROGRAMS {
+            let t = get(code);
+            let p = compile(&t).unwrap();
+            assert_eq!(Vm::new(&p).run().unwrap(), ev.eval(&t).unwrap(), "{}", code);
+        }
+
+        assert!(Vm::new(&compile(&get("(if 1 #T #F)")).unwrap()).run().is_err());
+        assert!(Vm::new(&compile(&get("((/lam x: #B, y: #B. x) #T)")).unwrap()).run().is_err());
+    }
+
+    #[test]
+    fn test_bench() {
+        let t = get("((/lam f: (#B -> #B). (f (f #T))) (/lam b: #B. (! b)))");
+        let b = bench(&t, 10, &Logger::root(Discard, o!())).unwrap();
+        assert_eq!(b.runs, 10);
+    }
+}
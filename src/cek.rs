@@ -0,0 +1,317 @@
+use std::fmt;
+use std::rc::Rc;
+
+use core::{Term, Ty};
+use errors::*;
+use eval::{self, StuckReason};
+
+/// Runtime values. Closures borrow their body from the program being run.
+#[derive(Clone, Debug)]
+pub enum Value<'a> {
+    True,
+    False,
+    Int(i64),
+    Not,
+    Closure(&'a [Ty], &'a Term, Env<'a>),
+}
+
+/// Innermost binding last, so `Var(k)` is `env[env.len() - k]`.
+pub type Env<'a> = Rc<Vec<Value<'a>>>;
+
+impl<'a> Value<'a> {
+    /// Converts a value back into a closed term, substituting closure environments.
+    pub fn readback(&self) -> Term {
+        match *self {
+            Value::True => Term::True,
+            Value::False => Term::False,
+            Value::Int(n) => Term::Int(n),
+            Value::Not => Term::Not,
+            Value::Closure(tys, body, ref env) => {
+                let env: Vec<Term> = env.iter().map(|v| v.readback()).collect();
+                eval::beta(&Term::abs(tys, body.clone()), &env)
+            }
+        }
+    }
+}
+
+/// A pending piece of work on the continuation stack.
+#[derive(Clone, Debug)]
+pub enum Frame<'a> {
+    /// Waiting for an `if` condition.
+    If(&'a Term, &'a Term, Env<'a>),
+    /// Waiting for the function of an application; arguments are still terms.
+    Fun(&'a [Term], Env<'a>),
+    /// Waiting for an argument: the function, the evaluated arguments and the rest.
+    Arg(Value<'a>, Vec<Value<'a>>, &'a [Term], Env<'a>),
+}
+
+#[derive(Clone, Debug)]
+pub enum Control<'a> {
+    Eval(&'a Term),
+    Return(Value<'a>),
+}
+
+#[derive(Clone, Debug)]
+pub struct State<'a> {
+    pub control: Control<'a>,
+    pub env: Env<'a>,
+    pub kont: Vec<Frame<'a>>,
+}
+
+impl<'a> fmt::Display for State<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.control {
+            Control::Eval(t) => write!(f, "eval {}", t.unparse())?,
+            Control::Return(ref v) => write!(f, "return {}", v.readback().unparse())?,
+        }
+        write!(f, " | env: {} | kont:", self.env.len())?;
+
+        for frame in self.kont.iter().rev() {
+            match *frame {
+                Frame::If(..) => write!(f, " if")?,
+                Frame::Fun(..) => write!(f, " fun")?,
+                Frame::Arg(_, ref done, rest, _) => write!(f, " arg{}", done.len() + rest.len())?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub enum Transition<'a> {
+    Continue,
+    Done(Value<'a>),
+    Stuck(StuckReason),
+}
+
+pub struct Machine<'a> {
+    state: State<'a>,
+    steps: u64,
+}
+
+impl<'a> Machine<'a> {
+    pub fn new(t: &'a Term) -> Machine<'a> {
+        Machine {
+            state: State {
+                control: Control::Eval(t),
+                env: Rc::new(Vec::new()),
+                kont: Vec::new(),
+            },
+            steps: 0,
+        }
+    }
+
+    pub fn state(&self) -> &State<'a> {
+        &self.state
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn step(&mut self) -> Transition<'a> {
+        self.steps += 1;
+
+        let control = match self.state.control {
+            Control::Eval(t) => self.eval(t),
+            Control::Return(ref v) => Ok(Control::Return(v.clone())),
+        };
+
+        let control = match control {
+            Ok(Control::Return(v)) => self.ret(v),
+            c => c,
+        };
+
+        match control {
+            Ok(Control::Return(ref v)) if self.state.kont.is_empty() => {
+                self.state.control = Control::Return(v.clone());
+                Transition::Done(v.clone())
+            }
+            Ok(c) => {
+                self.state.control = c;
+                Transition::Continue
+            }
+            Err(reason) => Transition::Stuck(reason),
+        }
+    }
+
+    pub fn run(&mut self) -> Result<Term> {
+        loop {
+            match self.step() {
+                Transition::Continue => {}
+                Transition::Done(v) => return Ok(v.readback()),
+                Transition::Stuck(reason) => {
+                    return Err(format!("CEK machine stuck after {} steps at {}: {}",
+                                       self.steps,
+                                       self.state,
+                                       reason)
+                                   .into())
+                }
+            }
+        }
+    }
+
+    fn eval(&mut self, t: &'a Term) -> ::std::result::Result<Control<'a>, StuckReason> {
+        let v = match *t {
+            Term::True => Value::True,
+            Term::False => Value::False,
+            Term::Int(n) => Value::Int(n),
+            Term::Not => Value::Not,
+            Term::Stuck => return Err(StuckReason::Bottom),
+            Term::Hole(ref x) => return Err(StuckReason::Hole(x.clone())),
+            Term::Var(k) => {
+                let env = &self.state.env;
+                return env.len()
+                           .checked_sub(k as usize)
+                           .and_then(|i| env.get(i))
+                           .map(|v| Control::Return(v.clone()))
+                           .ok_or(StuckReason::UnboundVariable(k));
+            }
+            Term::Abs(ref tys, ref body) => Value::Closure(tys, body, self.state.env.clone()),
+            Term::App(ref f, ref args) => {
+                self.state.kont.push(Frame::Fun(args, self.state.env.clone()));
+                return Ok(Control::Eval(f));
+            }
+            Term::If(ref cond, ref b1, ref b2) => {
+                self.state.kont.push(Frame::If(b1, b2, self.state.env.clone()));
+                return Ok(Control::Eval(cond));
+            }
+        };
+
+        Ok(Control::Return(v))
+    }
+
+    fn ret(&mut self, v: Value<'a>) -> ::std::result::Result<Control<'a>, StuckReason> {
+        let frame = match self.state.kont.pop() {
+            Some(frame) => frame,
+            None => return Ok(Control::Return(v)),
+        };
+
+        match frame {
+            Frame::If(b1, b2, env) => {
+                self.state.env = env;
+                match v {
+                    Value::True => Ok(Control::Eval(b1)),
+                    Value::False => Ok(Control::Eval(b2)),
+                    _ => Err(StuckReason::NonBoolCondition),
+                }
+            }
+            Frame::Fun(args, env) => {
+                if args.is_empty() {
+                    return self.apply(v, Vec::new());
+                }
+                self.state.kont.push(Frame::Arg(v, Vec::new(), &args[1..], env.clone()));
+                self.state.env = env;
+                Ok(Control::Eval(&args[0]))
+            }
+            Frame::Arg(f, mut done, rest, env) => {
+                done.push(v);
+                if rest.is_empty() {
+                    return self.apply(f, done);
+                }
+                self.state.kont.push(Frame::Arg(f, done, &rest[1..], env.clone()));
+                self.state.env = env;
+                Ok(Control::Eval(&rest[0]))
+            }
+        }
+    }
+
+    fn apply(&mut self,
+             f: Value<'a>,
+             args: Vec<Value<'a>>)
+             -> ::std::result::Result<Control<'a>, StuckReason> {
+        match f {
+            Value::Closure(tys, body, env) => {
+                if tys.len() != args.len() {
+                    return Err(StuckReason::ArityMismatch {
+                                   expected: tys.len(),
+                                   found: args.len(),
+                               });
+                }
+                let mut env = env.as_ref().clone();
+                env.extend(args);
+                self.state.env = Rc::new(env);
+                Ok(Control::Eval(body))
+            }
+            Value::Not => {
+                if args.len() != 1 {
+                    return Err(StuckReason::ArityMismatch {
+                                   expected: 1,
+                                   found: args.len(),
+                               });
+                }
+                match args[0] {
+                    Value::True => Ok(Control::Return(Value::False)),
+                    Value::False => Ok(Control::Return(Value::True)),
+                    _ => Err(StuckReason::NonBoolNegation),
+                }
+            }
+            _ => Err(StuckReason::NonFunction),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use slog::{Discard, Logger};
+
+    use core::Term;
+    use debrujin::RenameVisitor;
+    use eval::Evaluator;
+    use lispy;
+
+    fn get(s: &str) -> Term {
+        let tl = lispy::get_code(s.as_bytes()).unwrap();
+
+        let mut rv = RenameVisitor::new();
+        rv.rename_term(&tl).unwrap()
+    }
+
+    #[test]
+    fn test_cek() {
+        let mut ev = Evaluator::new(&Logger::root(Discard, o!()));
+
+        for code in &["#T",
+                      "(! #T)",
+                      "(if (! #T) 1 2)",
+                      "((/lam x: #B.x) #T)",
+                      "((/lam test: #B, val: #Int. (if test val -2000)) #T 2000)",
+                      "(((/lam x: #Int. (/lam y: #B. x)) 1) #F)",
+                      "((/lam x: #Int. (/lam y: #B. x)) 7)",
+                      "((/lam f: (#B -> #B). (f #F)) !)"] {
+            let t = get(code);
+            assert_eq!(Machine::new(&t).run().unwrap(), ev.eval(&t).unwrap());
+        }
+
+        assert!(Machine::new(&get("(if 1 #T #F)")).run().is_err());
+        assert!(Machine::new(&get("((/lam x: #B, y: #B. x) #T)")).run().is_err());
+    }
+
+    #[test]
+    fn test_states() {
+        let t = get("(! #T)");
+        let mut m = Machine::new(&t);
+
+        match m.step() {
+            Transition::Continue => {}
+            _ => panic!("expected to continue"),
+        }
+        assert_eq!(m.state().kont.len(), 1);
+
+        while let Transition::Continue = m.step() {}
+        assert!(m.state().kont.is_empty());
+        assert_eq!(m.state().to_string(), "return #F | env: 0 | kont:");
+    }
+
+    #[test]
+    fn test_deep() {
+        let mut t = Term::True;
+        for _ in 0..10000 {
+            t = Term::App(Box::new(Term::Not), vec![t]);
+        }
+
+        assert_eq!(Machine::new(&t).run().unwrap(), Term::True);
+    }
+}
@@ -0,0 +1,247 @@
+//! Closure conversion and lambda lifting.
+//!
+//! Every abstraction becomes a top-level `Function` that receives its
+//! arguments and an explicit environment holding only its free variables,
+//! and every occurrence of an abstraction becomes a `MakeClosure`. Types are
+//! erased.
+
+use core::Term;
+use errors::*;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Expr {
+    Int(i64),
+    Bool(bool),
+    /// `!` as a first-class function.
+    Not,
+    /// The n-th argument of the current function.
+    Param(usize),
+    /// The n-th captured value of the current closure.
+    Env(usize),
+    MakeClosure(usize, Vec<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+    Stuck,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Function {
+    pub arity: usize,
+    pub env_size: usize,
+    pub body: Expr,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub main: Expr,
+}
+
+/// Where the variables visible in a function body live.
+struct Scope {
+    arity: usize,
+    /// Free variables of the abstraction, relative to its enclosing scope.
+    captured: Vec<u32>,
+}
+
+impl Scope {
+    fn lookup(&self, k: u32) -> Expr {
+        let k = k as usize;
+        if k <= self.arity {
+            return Expr::Param(self.arity - k);
+        }
+
+        self.captured
+            .iter()
+            .position(|&v| v as usize == k - self.arity)
+            .map(Expr::Env)
+            .unwrap_or(Expr::Stuck)
+    }
+}
+
+struct Converter {
+    functions: Vec<Function>,
+}
+
+impl Converter {
+    fn expr(&mut self, t: &Term, scope: &Scope) -> Result<Expr> {
+        Ok(match *t {
+            Term::Int(n) => Expr::Int(n),
+            Term::True => Expr::Bool(true),
+            Term::False => Expr::Bool(false),
+            Term::Not => Expr::Not,
+            Term::Stuck => Expr::Stuck,
+            Term::Hole(ref x) => bail!("Cannot compile the unfilled hole ?{}", x),
+            Term::Var(k) => scope.lookup(k),
+            Term::App(ref f, ref args) => {
+                Expr::Call(Box::new(self.expr(f, scope)?),
+                           args.iter().map(|a| self.expr(a, scope)).collect::<Result<_>>()?)
+            }
+            Term::If(ref c, ref b1, ref b2) => {
+                Expr::If(Box::new(self.expr(c, scope)?),
+                         Box::new(self.expr(b1, scope)?),
+                         Box::new(self.expr(b2, scope)?))
+            }
+            Term::Abs(ref tys, ref body) => {
+                let inner = Scope {
+                    arity: tys.len(),
+                    captured: t.get_vars(),
+                };
+                let captured = inner.captured.iter().map(|&k| scope.lookup(k)).collect();
+
+                let idx = self.functions.len();
+                self.functions.push(Function {
+                                        arity: inner.arity,
+                                        env_size: inner.captured.len(),
+                                        body: Expr::Stuck,
+                                    });
+                self.functions[idx].body = self.expr(body, &inner)?;
+
+                Expr::MakeClosure(idx, captured)
+            }
+        })
+    }
+}
+
+pub fn convert(t: &Term) -> Result<Program> {
+    let mut c = Converter { functions: Vec::new() };
+    let top = Scope {
+        arity: 0,
+        captured: Vec::new(),
+    };
+    let main = c.expr(t, &top)?;
+
+    Ok(Program {
+           functions: c.functions,
+           main: main,
+       })
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Not,
+    Closure(usize, Vec<Value>),
+}
+
+impl Program {
+    pub fn run(&self) -> Result<Value> {
+        self.eval(&self.main, &[], &[])
+    }
+
+    fn eval(&self, e: &Expr, args: &[Value], env: &[Value]) -> Result<Value> {
+        let v = match *e {
+            Expr::Int(n) => Value::Int(n),
+            Expr::Bool(b) => Value::Bool(b),
+            Expr::Not => Value::Not,
+            Expr::Param(i) => args[i].clone(),
+            Expr::Env(i) => env[i].clone(),
+            Expr::Stuck => bail!("reached bottom"),
+            Expr::MakeClosure(f, ref captured) => {
+                let captured = captured.iter()
+                    .map(|c| self.eval(c, args, env))
+                    .collect::<Result<Vec<_>>>()?;
+                Value::Closure(f, captured)
+            }
+            Expr::If(ref c, ref b1, ref b2) => {
+                match self.eval(c, args, env)? {
+                    Value::Bool(true) => self.eval(b1, args, env)?,
+                    Value::Bool(false) => self.eval(b2, args, env)?,
+                    v => bail!("if condition {:?} is not a boolean", v),
+                }
+            }
+            Expr::Call(ref f, ref xs) => {
+                let f = self.eval(f, args, env)?;
+                let xs = xs.iter()
+                    .map(|x| self.eval(x, args, env))
+                    .collect::<Result<Vec<_>>>()?;
+
+                match f {
+                    Value::Closure(idx, ref captured) => {
+                        let func = &self.functions[idx];
+                        if func.arity != xs.len() {
+                            bail!("expected {} argument(s), found {}", func.arity, xs.len());
+                        }
+                        self.eval(&func.body, &xs, captured)?
+                    }
+                    Value::Not => {
+                        match (xs.len(), xs.first()) {
+                            (1, Some(&Value::Bool(b))) => Value::Bool(!b),
+                            _ => bail!("negated something other than one boolean"),
+                        }
+                    }
+                    v => bail!("applied a non-function {:?}", v),
+                }
+            }
+        };
+
+        Ok(v)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use slog::{Discard, Logger};
+
+    use super::*;
+    use core::{Term, TyLit};
+    use corpus::{get, PROGRAMS};
+    use eval::Evaluator;
+
+    #[test]
+    fn test_free_vars() {
+        assert_eq!(get("(/lam x: #B. x)").get_vars(), vec![]);
+        assert_eq!(Term::abs(&[], Term::app(Term::Var(2), &[Term::Var(1), Term::Var(2)]))
+                       .get_vars(),
+                   vec![1, 2]);
+        // Under the inner binder 1 is bound and 3 refers to the outer 2.
+        let inner = Term::abs(&[TyLit::Bool.into()],
+                              Term::if_(Term::Var(1), Term::Var(3), Term::Var(2)));
+        assert_eq!(inner.get_vars(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_convert() {
+        let p = convert(&get("(/lam x: #Int. (/lam y: #B. x))")).unwrap();
+
+        assert_eq!(p.main, Expr::MakeClosure(0, vec![]));
+        assert_eq!(p.functions,
+                   vec![Function {
+                            arity: 1,
+                            env_size: 0,
+                            body: Expr::MakeClosure(1, vec![Expr::Param(0)]),
+                        },
+                        Function {
+                            arity: 1,
+                            env_size: 1,
+                            body: Expr::Env(0),
+                        }]);
+
+        let e = convert(&Term::abs(&[], Term::Hole("h".into()))).unwrap_err();
+        assert_eq!(e.to_string(), "Cannot compile the unfilled hole ?h");
+    }
+
+    #[test]
+    fn test_run() {
+        let mut ev = Evaluator::new(&Logger::root(Discard, o!()));
+
+        for code in PROGRAMS {
+            let t = get(code);
+            let v = convert(&t).unwrap().run().unwrap();
+
+            match ev.eval(&t).unwrap() {
+                Term::Int(n) => assert_eq!(v, Value::Int(n), "{}", code),
+                Term::True => assert_eq!(v, Value::Bool(true), "{}", code),
+                Term::False => assert_eq!(v, Value::Bool(false), "{}", code),
+                Term::Not => assert_eq!(v, Value::Not, "{}", code),
+                _ => {
+                    match v {
+                        Value::Closure(..) => {}
+                        v => panic!("{} evaluated to {:?}", code, v),
+                    }
+                }
+            }
+        }
+    }
+}
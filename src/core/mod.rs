@@ -1,6 +1,5 @@
 pub mod term;
 pub mod ty;
-pub mod supervisitor;
 
 pub use self::ty::*;
 pub use self::term::*;
@@ -11,6 +11,8 @@ pub enum Term {
     Not,
     If(Box<Term>, Box<Term>, Box<Term>),
     Stuck,
+    /// A typed hole, see `typecheck::TypeCheckVisitor::holes`.
+    Hole(String),
 }
 
 impl Term {
@@ -55,39 +57,71 @@ impl Term {
                 format!("(If {} {} {})", cond.unparse(), b1.unparse(), b2.unparse())
             }
             &Term::Stuck => format!("#STUCK#"),
+            &Term::Hole(ref x) => format!("?{}", x),
         }
     }
 
-    // TODO
+    /// Free variables of the term, as indices into its enclosing scope, sorted
+    /// and without duplicates.
     pub fn get_vars(&self) -> Vec<u32> {
         let mut ret = Vec::new();
+        self.free_vars(0, &mut ret);
+        ret.sort();
+        ret.dedup();
 
+        ret
+    }
+
+    fn free_vars(&self, depth: u32, ret: &mut Vec<u32>) {
         match self {
             &Term::True => {}
             &Term::False => {}
             &Term::Not => {}
             &Term::Int(_) => {}
             &Term::Var(x) => {
-                ret.push(x);
+                if x > depth {
+                    ret.push(x - depth);
+                }
             }
             &Term::App(ref f, ref args) => {
+                f.free_vars(depth, ret);
                 for term in args {
-                    ret.extend(term.get_vars());
+                    term.free_vars(depth, ret);
                 }
-                ret.append(&mut f.get_vars());
             }
-            &Term::Abs(_, ref body) => {
-                ret.append(&mut body.get_vars());
+            &Term::Abs(ref tys, ref body) => {
+                body.free_vars(depth + tys.len() as u32, ret);
             }
             &Term::If(ref cond, ref b1, ref b2) => {
-                let (cv, b1v, b2v) = (cond.get_vars(), b1.get_vars(), b2.get_vars());
-
-                ret.extend(cv.iter().chain(b1v.iter()).chain(b2v.iter()));
+                cond.free_vars(depth, ret);
+                b1.free_vars(depth, ret);
+                b2.free_vars(depth, ret);
             }
             &Term::Stuck => {}
+            &Term::Hole(_) => {}
         };
+    }
 
-        ret
+    /// Names of the holes in the term, left to right.
+    pub fn holes(&self) -> Vec<&str> {
+        match *self {
+            Term::Hole(ref x) => vec![x.as_str()],
+            Term::Abs(_, ref body) => body.holes(),
+            Term::App(ref f, ref args) => {
+                let mut ret = f.holes();
+                for a in args {
+                    ret.extend(a.holes());
+                }
+                ret
+            }
+            Term::If(ref c, ref b1, ref b2) => {
+                let mut ret = c.holes();
+                ret.extend(b1.holes());
+                ret.extend(b2.holes());
+                ret
+            }
+            _ => Vec::new(),
+        }
     }
 
     pub fn app(f: Term, x: &[Term]) -> Term {
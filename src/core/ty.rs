@@ -23,7 +23,7 @@ impl TyLit {
     }
 }
 
-#[derive(Clone,PartialEq,Eq,Debug)]
+#[derive(Clone,PartialEq,Eq,Debug, Hash)]
 pub enum Ty {
     Base(TyLit),
     Arrow(Vec<Ty>, Box<Ty>),
@@ -56,6 +56,26 @@ impl Ty {
         }
     }
 
+    /// The type read as a proposition: base types are atoms, `->` is
+    /// implication and its parameters are joined by conjunction, and `_|_` is
+    /// falsity.
+    pub fn to_prop(&self) -> String {
+        match *self {
+            Ty::Base(TyLit::Bool) => "Bool".into(),
+            Ty::Base(TyLit::Int) => "Int".into(),
+            Ty::Arrow(ref params, ref res) => {
+                let params: Vec<String> = params.iter()
+                    .map(|p| match *p {
+                        Ty::Arrow(..) => format!("({})", p.to_prop()),
+                        _ => p.to_prop(),
+                    })
+                    .collect();
+                format!("{} → {}", params.join(" ∧ "), res.to_prop())
+            }
+            Ty::Bottom => "⊥".into(),
+        }
+    }
+
     pub fn arrow(t1: &[Ty], t2: Ty) -> Ty {
         Ty::Arrow(Vec::from(t1), Box::new(t2))
     }
@@ -0,0 +1,35 @@
+//! Closed, well-typed programs shared by the tests of the evaluators and passes.
+
+use core::Term;
+use debrujin::RenameVisitor;
+use lispy;
+
+pub const PROGRAMS: &'static [&'static str] =
+    &["#T",
+      "#F",
+      "42",
+      "!",
+      "(! #T)",
+      "(! (! #F))",
+      "(if (! #F) 1 2)",
+      "(if #F 1 (if #T 3 4))",
+      "(if (! #F) ! (/lam x: #B. x))",
+      "((/lam x: #B.x) #T)",
+      "((/lam x: #Int.x) 200)",
+      "(/lam x: #Int, t: #B. (if t x 0))",
+      "((/lam x: #B, y: #B. (if x y #F)) #T #T)",
+      "((/lam test: #B, val: #Int, dummy: #Int. (if test val -2000)) #T 2000 0)",
+      "((/lam x: #Int. (/lam y: #B. x)) 7)",
+      "(((/lam x: #Int. (/lam y: #B. x)) 1) #F)",
+      "((/lam f: (#B -> #B). (f #F)) !)",
+      "((/lam f: (#B -> #B). (f (f #T))) (/lam b: #B. (! b)))",
+      "(((/lam f: (#B -> #B). (/lam x: #B. (f (f x)))) !) #T)",
+      "((/lam g: (#B -> #Int). (g (! #T))) (/lam b: #B. (if b 1 0)))",
+      "(((/lam x: #Int, y: #Int. (/lam b: #B. (if b x y))) 3 4) #F)"];
+
+pub fn get(s: &str) -> Term {
+    let tl = lispy::get_code(s.as_bytes()).unwrap();
+
+    let mut rv = RenameVisitor::new();
+    rv.rename_term(&tl).unwrap()
+}
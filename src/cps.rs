@@ -0,0 +1,248 @@
+//! Typed call-by-value CPS translation.
+//!
+//! A term of type `A` becomes a computation of type `(V(A) -> o) -> o`, where
+//! `V` maps base types to themselves and `A1, ..., An -> B` to
+//! `V(A1), ..., V(An), (V(B) -> o) -> o`. The type language has no
+//! quantifiers, so the answer type `o` is chosen by the caller instead of
+//! being bound by a `forall`.
+//!
+//! The translation is generated with de Bruijn levels and converted to
+//! indices at the end, and its output is re-checked by `TypeCheckVisitor`.
+
+use core::{Term, Ty, TyLit};
+use errors::*;
+use typecheck::TypeCheckVisitor;
+
+/// The CPS image of a value type.
+pub fn cps_ty(ty: &Ty, answer: &Ty) -> Ty {
+    match *ty {
+        Ty::Arrow(ref args, ref res) => {
+            let mut args: Vec<Ty> = args.iter().map(|a| cps_ty(a, answer)).collect();
+            args.push(cont_ty(res, answer));
+            Ty::arrow(&args, answer.clone())
+        }
+        ref ty => ty.clone(),
+    }
+}
+
+/// The type of a continuation expecting a value of type `ty`.
+fn cont_ty(ty: &Ty, answer: &Ty) -> Ty {
+    Ty::arrow(&[cps_ty(ty, answer)], answer.clone())
+}
+
+/// The CPS image of a computation of type `ty`.
+pub fn computation_ty(ty: &Ty, answer: &Ty) -> Ty {
+    Ty::arrow(&[cont_ty(ty, answer)], answer.clone())
+}
+
+struct Translator<'a> {
+    answer: &'a Ty,
+    /// Source variables, innermost last, with their output level and source type.
+    env: Vec<(u32, Ty)>,
+}
+
+impl<'a> Translator<'a> {
+    /// Builds `(/lam k: V(ty) -> o. body)`. When placed at output depth `d`,
+    /// `k` has level `d` and `body` must be built for depth `d + 1`.
+    fn comp(&self, ty: &Ty, body: Term) -> Term {
+        Term::abs(&[cont_ty(ty, self.answer)], body)
+    }
+
+    /// `[[t]]` at output depth `d`, together with the source type of `t`.
+    fn term(&mut self, t: &Term, d: u32) -> Result<(Term, Ty)> {
+        match *t {
+            Term::True | Term::False => {
+                let ty = TyLit::Bool.into();
+                let c = self.comp(&ty, Term::app(Term::Var(d), &[t.clone()]));
+                Ok((c, ty))
+            }
+            Term::Int(_) => {
+                let ty = TyLit::Int.into();
+                let c = self.comp(&ty, Term::app(Term::Var(d), &[t.clone()]));
+                Ok((c, ty))
+            }
+            Term::Hole(ref x) => bail!("Cannot compile the unfilled hole ?{}", x),
+            Term::Stuck => {
+                let c = self.comp(&Ty::Bottom, Term::app(Term::Var(d), &[Term::Stuck]));
+                Ok((c, Ty::Bottom))
+            }
+            Term::Var(i) => {
+                let (level, ty) = self.env[self.env.len() - i as usize].clone();
+                let c = self.comp(&ty, Term::app(Term::Var(d), &[Term::Var(level)]));
+                Ok((c, ty))
+            }
+            Term::Not => {
+                let b: Ty = TyLit::Bool.into();
+                let ty = Ty::arrow(&[b.clone()], b.clone());
+                let k2 = cont_ty(&b, self.answer);
+                // (/lam b: #B, k2: #B -> o. (k2 (! b))) with b at d + 1 and k2 at d + 2
+                let not = Term::abs(&[b, k2],
+                                    Term::app(Term::Var(d + 2),
+                                              &[Term::app(Term::Not, &[Term::Var(d + 1)])]));
+                let c = self.comp(&ty, Term::app(Term::Var(d), &[not]));
+                Ok((c, ty))
+            }
+            Term::Abs(ref tys, ref body) => {
+                let n = tys.len() as u32;
+                for (i, ty) in tys.iter().enumerate() {
+                    self.env.push((d + 1 + i as u32, ty.clone()));
+                }
+                let res = self.term(body, d + n + 2);
+                for _ in tys {
+                    self.env.pop();
+                }
+                let (body, res) = res?;
+
+                let mut params: Vec<Ty> = tys.iter().map(|ty| cps_ty(ty, self.answer)).collect();
+                params.push(cont_ty(&res, self.answer));
+                let f = Term::abs(&params, Term::app(body, &[Term::Var(d + n + 1)]));
+
+                let ty = Ty::arrow(tys, res);
+                let c = self.comp(&ty, Term::app(Term::Var(d), &[f]));
+                Ok((c, ty))
+            }
+            Term::App(ref f, ref args) => {
+                let (fc, fty) = self.term(f, d + 1)?;
+                let (params, res) = match fty {
+                    Ty::Arrow(ref params, ref res) => (params.clone(), res.as_ref().clone()),
+                    _ => unreachable!("the term typechecks"),
+                };
+
+                // f has level d + 1 and the i-th argument level d + 2 + i.
+                let body = self.args(args, &params, d + 1, d + 2)?;
+                let k = Term::abs(&[cps_ty(&fty, self.answer)], body);
+                let c = self.comp(&res, Term::app(fc, &[k]));
+                Ok((c, res))
+            }
+            Term::If(ref cond, ref b1, ref b2) => {
+                let (cc, _) = self.term(cond, d + 1)?;
+                let (c1, t1) = self.term(b1, d + 2)?;
+                let (c2, _) = self.term(b2, d + 2)?;
+
+                let branch = Term::if_(Term::Var(d + 1),
+                                       Term::app(c1, &[Term::Var(d)]),
+                                       Term::app(c2, &[Term::Var(d)]));
+                let k = Term::abs(&[TyLit::Bool.into()], branch);
+                let c = self.comp(&t1, Term::app(cc, &[k]));
+                Ok((c, t1))
+            }
+        }
+    }
+
+    /// Evaluates `args` in order, binding each at the next level, then calls
+    /// the function at level `f` with them and the continuation at level `f - 1`.
+    fn args(&mut self, args: &[Term], params: &[Ty], f: u32, d: u32) -> Result<Term> {
+        match args.split_first() {
+            None => {
+                let n = params.len() as u32;
+                let mut xs: Vec<Term> = (0..n).map(|i| Term::Var(f + 1 + i)).collect();
+                xs.push(Term::Var(f - 1));
+                Ok(Term::app(Term::Var(f), &xs))
+            }
+            Some((arg, rest)) => {
+                let (ac, aty) = self.term(arg, d)?;
+                let k = self.args(rest, params, f, d + 1)?;
+                Ok(Term::app(ac, &[Term::abs(&[cps_ty(&aty, self.answer)], k)]))
+            }
+        }
+    }
+}
+
+/// Replaces de Bruijn levels by indices.
+fn to_indices(t: &Term, depth: u32) -> Term {
+    match *t {
+        Term::Var(level) => Term::Var(depth - level),
+        Term::Abs(ref tys, ref body) => Term::abs(tys, to_indices(body, depth + tys.len() as u32)),
+        Term::App(ref f, ref args) => {
+            Term::App(Box::new(to_indices(f, depth)),
+                      args.iter().map(|a| to_indices(a, depth)).collect())
+        }
+        Term::If(ref c, ref b1, ref b2) => {
+            Term::if_(to_indices(c, depth),
+                      to_indices(b1, depth),
+                      to_indices(b2, depth))
+        }
+        ref t => t.clone(),
+    }
+}
+
+/// Translates a closed term to CPS with answer type `answer`, returning the
+/// translation and its type. The term is typechecked first, so an ill-typed
+/// term fails with its `TypeError`. Fails if the output does not typecheck.
+pub fn cps(t: &Term, answer: &Ty) -> Result<(Term, Ty)> {
+    TypeCheckVisitor::new().type_of(t)?;
+
+    let (c, ty) = {
+        let mut tr = Translator {
+            answer: answer,
+            env: Vec::new(),
+        };
+        tr.term(t, 0)?
+    };
+
+    let c = to_indices(&c, 0);
+    let expected = computation_ty(&ty, answer);
+    let found = TypeCheckVisitor::new().type_of(&c)?;
+
+    if found != expected {
+        bail!("CPS self-check failed: expected {}, found {}", expected, found);
+    }
+
+    Ok((c, expected))
+}
+
+/// Translates a closed term of base type and applies it to the identity continuation.
+pub fn cps_program(t: &Term) -> Result<Term> {
+    let ty = TypeCheckVisitor::new().type_of(t)?;
+    match ty {
+        Ty::Base(_) => {}
+        ty => bail!("Can only run CPS programs of base type, not {}", ty),
+    }
+
+    let (c, _) = cps(t, &ty)?;
+    Ok(Term::app(c, &[Term::abs(&[ty], Term::Var(1))]))
+}
+
+#[cfg(test)]
+mod test {
+    use slog::{Discard, Logger};
+
+    use super::*;
+    use core::{Ty, TyLit};
+    use corpus::{get, PROGRAMS};
+    use eval::Evaluator;
+    use typecheck::TypeCheckVisitor;
+
+    #[test]
+    fn test_cps_ty() {
+        let b: Ty = TyLit::Bool.into();
+        let o: Ty = TyLit::Int.into();
+
+        assert_eq!(cps_ty(&b, &o), b);
+        assert_eq!(cps_ty(&Ty::arrow(&[b.clone()], b.clone()), &o).unparse(),
+                   "(#B, (#B -> #Int) -> #Int)");
+        assert_eq!(computation_ty(&b, &o).unparse(), "((#B -> #Int) -> #Int)");
+    }
+
+    #[test]
+    fn test_cps() {
+        let mut ev = Evaluator::new(&Logger::root(Discard, o!()));
+
+        for code in PROGRAMS {
+            let t = get(code);
+            let ty = TypeCheckVisitor::new().type_of(&t).unwrap();
+
+            let (_, cty) = cps(&t, &TyLit::Bool.into()).unwrap();
+            assert_eq!(cty, computation_ty(&ty, &TyLit::Bool.into()));
+
+            if let Ty::Base(_) = ty {
+                let p = cps_program(&t).unwrap();
+                assert_eq!(ev.eval(&p).unwrap(), ev.eval(&t).unwrap(), "{}", code);
+            }
+        }
+
+        let e = cps(&get("((/lam x: #B. x) 1)"), &TyLit::Bool.into()).unwrap_err();
+        assert_eq!(e.to_string(),
+                   "type error: Argument 1 of ((/lam : #B. 1) 1) has type #Int but #B was expected");
+    }
+}
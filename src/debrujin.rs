@@ -11,6 +11,8 @@ type BVar = String;
 pub struct RenameVisitor {
     vs: HashMap<BVar, Vec<u32>>,
     state: Vec<Term>,
+    /// Whether the term contains a `toplevel::Term::Error`.
+    syntax_error: bool,
 }
 
 impl RenameVisitor {
@@ -18,12 +20,17 @@ impl RenameVisitor {
         RenameVisitor {
             vs: HashMap::new(),
             state: Vec::new(),
+            syntax_error: false,
         }
     }
 
     pub fn rename_term(&mut self, term: &TTerm) -> Result<Term> {
         v::walk_term(self, term);
-        let ret = self.state.pop().ok_or(rename_err("something weird"));
+        let ret = if self.syntax_error {
+            Err(rename_err("cannot rename a term with syntax errors"))
+        } else {
+            self.state.pop().ok_or(rename_err("something weird"))
+        };
         self.reset();
         ret
     }
@@ -31,9 +38,73 @@ impl RenameVisitor {
     fn reset(&mut self) {
         self.vs.clear();
         self.state.clear();
+        self.syntax_error = false;
     }
 }
 
+/// Turns indices back into names, the inverse of `RenameVisitor` up to the
+/// choice of names. Binders are named by how deeply they are nested (`x`, `y`,
+/// `z`, `w`, `x1`, ...), so no name is ever shadowed.
+pub fn name_term(term: &Term) -> Result<TTerm> {
+    name_term_in(term, &[])
+}
+
+/// The name `name_term` gives the binder at `level`: x, y, z, w, x1, y1, ...
+pub fn var_name(level: usize) -> String {
+    let base = ["x", "y", "z", "w"][level % 4];
+    match level / 4 {
+        0 => base.into(),
+        n => format!("{}{}", base, n),
+    }
+}
+
+/// Like `name_term`, for a term whose free variables are bound by `scope`,
+/// innermost last. Binders skip the names already in scope.
+pub fn name_term_in(term: &Term, scope: &[String]) -> Result<TTerm> {
+    fn go(t: &Term, scope: &mut Vec<String>) -> Result<TTerm> {
+        Ok(match *t {
+            Term::Var(k) => {
+                let name = scope.len()
+                    .checked_sub(k as usize)
+                    .and_then(|i| scope.get(i))
+                    .ok_or(rename_err("free variable"))?;
+                TTerm::Var(name.clone())
+            }
+            Term::Abs(ref tys, ref body) => {
+                let mut level = scope.len();
+                let mut args: Vec<(String, Ty)> = Vec::new();
+                for ty in tys {
+                    while scope.contains(&var_name(level)) {
+                        level += 1;
+                    }
+                    scope.push(var_name(level));
+                    args.push((var_name(level), ty.clone()));
+                }
+
+                let body = go(body, scope);
+                let len = scope.len() - tys.len();
+                scope.truncate(len);
+                TTerm::Abs(args, Box::new(body?))
+            }
+            Term::App(ref f, ref args) => {
+                TTerm::App(Box::new(go(f, scope)?),
+                           args.iter().map(|a| go(a, scope)).collect::<Result<_>>()?)
+            }
+            Term::True => TTerm::True,
+            Term::False => TTerm::False,
+            Term::Int(n) => TTerm::Int(n),
+            Term::Not => TTerm::Not,
+            Term::If(ref c, ref b1, ref b2) => {
+                TTerm::if_(go(c, scope)?, go(b1, scope)?, go(b2, scope)?)
+            }
+            Term::Stuck => TTerm::Bottom,
+            Term::Hole(ref x) => TTerm::Hole(x.clone()),
+        })
+    }
+
+    go(term, &mut scope.to_vec())
+}
+
 impl<'a> Visitor<'a> for RenameVisitor {
     fn visit_true(&mut self) {
         self.state.push(Term::True);
@@ -51,6 +122,16 @@ impl<'a> Visitor<'a> for RenameVisitor {
         self.state.push(Term::Stuck);
     }
 
+    fn visit_hole(&mut self, x: &'a str) {
+        self.state.push(Term::Hole(x.into()));
+    }
+
+    fn visit_error(&mut self) {
+        // Keeps the stack in shape; `rename_term` fails anyway.
+        self.syntax_error = true;
+        self.state.push(Term::Stuck);
+    }
+
     fn visit_int(&mut self, n: i64) {
         self.state.push(Term::Int(n));
     }
@@ -136,7 +217,7 @@ impl<'a> Visitor<'a> for RenameVisitor {
 
 #[cfg(test)]
 mod test {
-    use super::RenameVisitor;
+    use super::{name_term, name_term_in, RenameVisitor};
     use toplevel::Term;
     use lispy::scan::parse;
     use lispy::tokenize::scan;
@@ -173,4 +254,35 @@ mod test {
             .unwrap();
         assert_eq!("(/lam : #B. (/lam : #B. 2))", t.unparse());
     }
+
+    #[test]
+    fn test_syntax_error() {
+        let partial = ::lispy::scan::parse_recovering(scan(b"((/lam x: #B. x) (! :))").unwrap());
+        assert_eq!(partial.term.pretty(), "((/lam x: #B. x) (! <error>))");
+
+        let mut rv = RenameVisitor::new();
+        assert_eq!(rv.rename_term(&partial.term).unwrap_err().to_string(),
+                   ::errors::rename_err("cannot rename a term with syntax errors").to_string());
+        assert!(rv.rename_term(&get("(/lam x: #B. x)")).is_ok());
+    }
+
+    #[test]
+    fn test_name_term() {
+        let mut rv = RenameVisitor::new();
+
+        let t = rv.rename_term(&get("(/lam a: #B. (/lam b: #B, c: #Int. (if b c 0)))")).unwrap();
+        let named = name_term(&t).unwrap();
+        assert_eq!(named.pretty(), "(/lam x: #B. (/lam y: #B, z: #Int. (if y z 0)))");
+        assert_eq!(rv.rename_term(&named).unwrap(), t);
+
+        assert!(name_term(&::core::Term::Var(1)).is_err());
+
+        let open = rv.rename_term(&get("(/lam f: (#B -> #B). (/lam b: #B. (f b)))")).unwrap();
+        let open = match open {
+            ::core::Term::Abs(_, body) => *body,
+            _ => unreachable!(),
+        };
+        assert_eq!(name_term_in(&open, &["y".into()]).unwrap().pretty(), "(/lam z: #B. (y z))");
+        assert_eq!(name_term_in(&open, &["x".into()]).unwrap().pretty(), "(/lam y: #B. (x y))");
+    }
 }
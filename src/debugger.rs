@@ -0,0 +1,237 @@
+//! Single-stepping through `eval::step`, for the REPL's debugger commands.
+
+use core::Term;
+use eval::{self, Rule, Step, StuckReason};
+
+/// The subterm of `t` at `path`, numbered as in `eval::redex`.
+pub fn subterm<'a>(t: &'a Term, path: &[usize]) -> Option<&'a Term> {
+    let (&i, rest) = match path.split_first() {
+        Some(p) => p,
+        None => return Some(t),
+    };
+
+    let child = match *t {
+        Term::Abs(_, ref body) if i == 0 => body,
+        Term::App(ref f, _) if i == 0 => f,
+        Term::App(_, ref args) => args.get(i - 1)?,
+        Term::If(ref c, _, _) if i == 0 => c,
+        Term::If(_, ref b1, _) if i == 1 => b1,
+        Term::If(_, _, ref b2) if i == 2 => b2,
+        _ => return None,
+    };
+
+    subterm(child, rest)
+}
+
+/// Unparses `t` like `Term::unparse`, except that the subterm at `path` is
+/// replaced by `mark` applied to it.
+pub fn unparse_marked<F>(t: &Term, path: &[usize], mark: &F) -> String
+    where F: Fn(&Term) -> String
+{
+    let (&i, rest) = match path.split_first() {
+        Some(p) => p,
+        None => return mark(t),
+    };
+    let child = |j: usize, c: &Term| if i == j {
+        unparse_marked(c, rest, mark)
+    } else {
+        c.unparse()
+    };
+
+    match *t {
+        Term::Abs(ref tys, ref body) => {
+            let tys: Vec<String> = tys.iter().map(|ty| format!(": {}", ty.unparse())).collect();
+            format!("(/lam {}. {})", tys.join(", "), child(0, body))
+        }
+        Term::App(ref f, ref args) => {
+            let args: Vec<String> = args.iter().enumerate().map(|(j, a)| child(j + 1, a)).collect();
+            format!("({} {})", child(0, f), args.join(" "))
+        }
+        Term::If(ref c, ref b1, ref b2) => {
+            format!("(If {} {} {})", child(0, c), child(1, b1), child(2, b2))
+        }
+        ref t => t.unparse(),
+    }
+}
+
+/// What happened when the debugger stopped.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    /// Took a step with this rule and paused.
+    Stepped(Rule),
+    /// Stopped before a redex matching the breakpoint with this number.
+    Breakpoint(usize),
+    Value,
+    Stuck(StuckReason),
+}
+
+pub struct Debugger {
+    term: Term,
+    steps: u64,
+    /// Matched against the rule name and the unparsed redex.
+    breakpoints: Vec<String>,
+}
+
+impl Debugger {
+    pub fn new(t: &Term) -> Debugger {
+        Debugger {
+            term: t.clone(),
+            steps: 0,
+            breakpoints: Vec::new(),
+        }
+    }
+
+    /// Starts debugging `t`, keeping this session's breakpoints.
+    pub fn restart(&mut self, t: &Term) {
+        self.term = t.clone();
+        self.steps = 0;
+    }
+
+    pub fn term(&self) -> &Term {
+        &self.term
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Adds a breakpoint and returns its number.
+    pub fn add_breakpoint(&mut self, pattern: &str) -> usize {
+        self.breakpoints.push(pattern.into());
+        self.breakpoints.len()
+    }
+
+    pub fn breakpoints(&self) -> &[String] {
+        &self.breakpoints
+    }
+
+    pub fn redex(&self) -> Option<&Term> {
+        eval::redex(&self.term).and_then(|path| subterm(&self.term, &path))
+    }
+
+    /// Takes one reduction step.
+    pub fn step(&mut self) -> Event {
+        match eval::step(&self.term) {
+            Step::Value => Event::Value,
+            Step::Stuck(_, reason) => Event::Stuck(reason),
+            Step::Reduced(t, rule) => {
+                self.term = t;
+                self.steps += 1;
+                Event::Stepped(rule)
+            }
+        }
+    }
+
+    /// Steps until the current redex, and whatever it reduces to, is a value.
+    pub fn next(&mut self) -> Event {
+        let path = match eval::redex(&self.term) {
+            Some(path) => path,
+            None => return Event::Value,
+        };
+
+        let mut event = self.step();
+        while let Event::Stepped(_) = event {
+            let inside = eval::redex(&self.term).map_or(false, |p| p.starts_with(&path));
+            if !inside {
+                break;
+            }
+            event = self.step();
+        }
+
+        event
+    }
+
+    /// Steps until the term is a value, gets stuck or the next redex matches
+    /// a breakpoint. Always takes at least one step.
+    pub fn continue_(&mut self) -> Event {
+        let mut event = self.step();
+        while let Event::Stepped(_) = event {
+            if let Some(i) = self.hit() {
+                return Event::Breakpoint(i);
+            }
+            event = self.step();
+        }
+
+        event
+    }
+
+    fn hit(&self) -> Option<usize> {
+        let redex = self.redex()?.unparse();
+        let rule = match eval::step(&self.term) {
+            Step::Reduced(_, rule) => rule.to_string(),
+            _ => return None,
+        };
+
+        self.breakpoints
+            .iter()
+            .position(|b| *b == rule || redex.contains(b.as_str()))
+            .map(|i| i + 1)
+    }
+
+    /// The term with the redex highlighted, the evaluation context around it
+    /// and the redex itself.
+    pub fn where_(&self) -> String {
+        let path = match eval::redex(&self.term) {
+            Some(path) => path,
+            None => return format!("[{}] value: {}", self.steps, self.term.unparse()),
+        };
+
+        format!("[{}] {}\n  context: {}\n  redex:   {}",
+                self.steps,
+                unparse_marked(&self.term, &path, &|t| format!("{{{}}}", t.unparse())),
+                unparse_marked(&self.term, &path, &|_| "[]".into()),
+                subterm(&self.term, &path).unwrap().unparse())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use corpus::{get, PROGRAMS};
+
+    #[test]
+    fn test_redex() {
+        for code in PROGRAMS {
+            let mut t = get(code);
+
+            while let Some(path) = eval::redex(&t) {
+                let sub = subterm(&t, &path).unwrap();
+                assert_eq!(unparse_marked(&t, &path, &|t| t.unparse()), t.unparse());
+
+                t = match (eval::step(&t), eval::step(sub)) {
+                    (Step::Reduced(t, r1), Step::Reduced(_, r2)) => {
+                        assert_eq!(r1, r2, "{}", code);
+                        t
+                    }
+                    (s1, s2) => panic!("{}: {:?} {:?}", code, s1, s2),
+                };
+            }
+        }
+    }
+
+    #[test]
+    fn test_debugger() {
+        let t = get("((/lam x: #B. (if x 1 2)) (! (! #T)))");
+        let mut d = Debugger::new(&t);
+
+        assert_eq!(d.where_(),
+                   "[0] ((/lam : #B. (If 1 1 2)) (! {(! #T)}))\n  context: ((/lam : #B. (If 1 1 \
+                    2)) (! []))\n  redex:   (! #T)");
+        assert_eq!(d.step(), Event::Stepped(Rule::Not));
+        assert_eq!(d.redex(), Some(&get("(! #F)")));
+
+        assert_eq!(d.next(), Event::Stepped(Rule::Not));
+        assert_eq!(d.term(), &get("((/lam x: #B. (if x 1 2)) #T)"));
+
+        d.add_breakpoint("if-true");
+        assert_eq!(d.continue_(), Event::Breakpoint(1));
+        assert_eq!(d.term(), &get("(if #T 1 2)"));
+        assert_eq!(d.continue_(), Event::Value);
+        assert_eq!(d.where_(), "[4] value: 1");
+
+        d.restart(&get("((/lam x: #B. x) (if 1 #T #F))"));
+        assert_eq!(d.breakpoints().len(), 1);
+        assert_eq!(d.next(), Event::Stuck(StuckReason::NonBoolCondition));
+        assert_eq!(d.steps(), 0);
+    }
+}
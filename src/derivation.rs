@@ -0,0 +1,248 @@
+//! Typing derivations recorded by `TypeCheckVisitor::derive`, rendered as
+//! ASCII proof trees or as a LaTeX document using `bussproofs`.
+
+use std::cmp;
+use std::fmt;
+
+use core::{Term, Ty};
+use errors::*;
+use typecheck::{show_context, TypeEnv};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TypingRule {
+    True,
+    False,
+    Int,
+    Not,
+    Bottom,
+    Hole,
+    Var,
+    Abs,
+    App,
+    If,
+}
+
+impl TypingRule {
+    /// The rule concluding a judgement about `t`.
+    pub fn of(t: &Term) -> TypingRule {
+        match *t {
+            Term::True => TypingRule::True,
+            Term::False => TypingRule::False,
+            Term::Int(_) => TypingRule::Int,
+            Term::Not => TypingRule::Not,
+            Term::Stuck => TypingRule::Bottom,
+            Term::Hole(_) => TypingRule::Hole,
+            Term::Var(_) => TypingRule::Var,
+            Term::Abs(..) => TypingRule::Abs,
+            Term::App(..) => TypingRule::App,
+            Term::If(..) => TypingRule::If,
+        }
+    }
+}
+
+impl fmt::Display for TypingRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            TypingRule::True => "T-True",
+            TypingRule::False => "T-False",
+            TypingRule::Int => "T-Int",
+            TypingRule::Not => "T-Not",
+            TypingRule::Bottom => "T-Bottom",
+            TypingRule::Hole => "T-Hole",
+            TypingRule::Var => "T-Var",
+            TypingRule::Abs => "T-Abs",
+            TypingRule::App => "T-App",
+            TypingRule::If => "T-If",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Derivation {
+    pub rule: TypingRule,
+    pub context: TypeEnv,
+    pub term: Term,
+    /// `None` when the type depends on a hole whose type is not determined.
+    pub ty: Option<Ty>,
+    pub premises: Vec<Derivation>,
+}
+
+/// Space between premises placed side by side.
+const GAP: usize = 3;
+
+impl Derivation {
+    /// The judgement `context |- term : ty`.
+    pub fn judgement(&self) -> String {
+        let context = show_context(&self.context);
+        let sep = if context.is_empty() { "" } else { " " };
+        format!("{}{}|- {} : {}", context, sep, self.term.unparse(), self.show_ty())
+    }
+
+    /// The type, or `?` when it is not determined.
+    pub fn show_ty(&self) -> String {
+        self.ty.as_ref().map_or("?".into(), Ty::unparse)
+    }
+
+    /// Lines of the tree, all of the same width, conclusion last.
+    fn boxed(&self) -> Vec<String> {
+        let premises: Vec<Vec<String>> = self.premises.iter().map(|p| p.boxed()).collect();
+        let height = premises.iter().map(|p| p.len()).max().unwrap_or(0);
+
+        // Bottom-align the premises and put them side by side.
+        let mut above = vec![String::new(); height];
+        for (i, p) in premises.iter().enumerate() {
+            let width = p[0].chars().count();
+            for (row, line) in above.iter_mut().enumerate() {
+                if i > 0 {
+                    line.push_str(&" ".repeat(GAP));
+                }
+                match (row + p.len()).checked_sub(height) {
+                    Some(r) => line.push_str(&p[r]),
+                    None => line.push_str(&" ".repeat(width)),
+                }
+            }
+        }
+
+        let conclusion = self.judgement();
+        let above_width = above.first().map_or(0, |l| l.chars().count());
+        let bar_width = cmp::max(above_width, conclusion.chars().count());
+        let label = format!(" {}", self.rule);
+        let width = bar_width + label.len();
+
+        let center = |s: &str| {
+            let pad = (bar_width - s.chars().count()) / 2;
+            let line = format!("{}{}", " ".repeat(pad), s);
+            let fill = width - line.chars().count();
+            line + &" ".repeat(fill)
+        };
+
+        let mut lines: Vec<String> = above.iter().map(|l| center(l)).collect();
+        lines.push(format!("{}{}", "-".repeat(bar_width), label));
+        lines.push(center(&conclusion));
+        lines
+    }
+
+    /// The derivation as an ASCII proof tree, premises above the line.
+    pub fn to_ascii(&self) -> String {
+        let lines: Vec<String> = self.boxed().iter().map(|l| l.trim_right().to_string()).collect();
+        lines.join("\n")
+    }
+
+    fn latex_into(&self, out: &mut String) -> Result<()> {
+        if self.premises.is_empty() {
+            out.push_str("\\AxiomC{}\n");
+        }
+        for p in &self.premises {
+            p.latex_into(out)?;
+        }
+
+        let inference = match self.premises.len() {
+            0 | 1 => "UnaryInfC",
+            2 => "BinaryInfC",
+            3 => "TrinaryInfC",
+            4 => "QuaternaryInfC",
+            5 => "QuinaryInfC",
+            n => bail!("bussproofs supports at most 5 premises, {} has {}", self.term.unparse(), n),
+        };
+
+        let context = latex_escape(&show_context(&self.context));
+        let context = if context.is_empty() {
+            String::new()
+        } else {
+            format!("\\texttt{{{}}} ", context)
+        };
+
+        out.push_str(&format!("\\RightLabel{{\\scriptsize {}}}\n", self.rule));
+        out.push_str(&format!("\\{}{{${}\\vdash \\texttt{{{}}} : \\texttt{{{}}}$}}\n",
+                              inference,
+                              context,
+                              latex_escape(&self.term.unparse()),
+                              latex_escape(&self.show_ty())));
+        Ok(())
+    }
+
+    /// A standalone LaTeX document typesetting the derivation with `bussproofs`.
+    pub fn to_latex(&self) -> Result<String> {
+        let mut tree = String::new();
+        self.latex_into(&mut tree)?;
+
+        Ok(format!("\\documentclass{{article}}\n\\usepackage{{bussproofs}}\n\\begin{{document}}\n\
+                    \\begin{{prooftree}}\n{}\\end{{prooftree}}\n\\end{{document}}\n",
+                   tree))
+    }
+}
+
+fn latex_escape(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '#' | '_' | '{' | '}' | '$' | '%' | '&' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\\' => out.push_str("\\textbackslash{}"),
+            '|' => out.push_str("\\textbar{}"),
+            '>' => out.push_str("\\textgreater{}"),
+            '<' => out.push_str("\\textless{}"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use corpus::{get, PROGRAMS};
+    use typecheck::TypeCheckVisitor;
+
+    fn count(d: &Derivation) -> usize {
+        1 + d.premises.iter().map(count).sum::<usize>()
+    }
+
+    #[test]
+    fn test_derive() {
+        for code in PROGRAMS {
+            let t = get(code);
+            let d = TypeCheckVisitor::new().derive(&t).unwrap();
+
+            assert_eq!(d.term, t);
+            assert_eq!(d.ty, Some(TypeCheckVisitor::new().type_of(&t).unwrap()));
+            assert_eq!(count(&d), ::opt::size(&t), "{}", code);
+            assert!(d.to_latex().is_ok());
+        }
+
+        let d = TypeCheckVisitor::new().derive(&get("(/lam x: #B. (! x))")).unwrap();
+        assert_eq!(d.rule, TypingRule::Abs);
+        assert_eq!(d.premises[0].context, vec![Ty::Base(::core::TyLit::Bool)]);
+        assert_eq!(d.premises[0].premises[1].rule, TypingRule::Var);
+
+        assert!(TypeCheckVisitor::new().derive(&get("(if 1 #T #F)")).is_err());
+
+        // Types that depend on an undetermined hole are not made up.
+        let d = TypeCheckVisitor::new().derive(&get("(/lam x: #B. ?h)")).unwrap();
+        assert_eq!(d.judgement(), "|- (/lam : #B. ?h) : ?");
+        assert_eq!(d.premises[0].ty, None);
+    }
+
+    #[test]
+    fn test_render() {
+        let d = TypeCheckVisitor::new().derive(&get("(/lam x: #B. (! x))")).unwrap();
+
+        assert_eq!(d.to_ascii(),
+                   "----------------------- T-Not   --------------- T-Var
+1: #B |- ! : (#B -> #B)         1: #B |- 1 : #B
+----------------------------------------------------- T-App
+                 1: #B |- (! 1) : #B
+----------------------------------------------------------- T-Abs
+            |- (/lam : #B. (! 1)) : (#B -> #B)");
+
+        let tex = d.to_latex().unwrap();
+        assert!(tex.contains("\\usepackage{bussproofs}"));
+        assert!(tex.contains("\\BinaryInfC{$\\texttt{1: \\#B} \\vdash \\texttt{(! 1)} : \
+                              \\texttt{\\#B}$}"));
+        assert!(tex.contains("\\UnaryInfC{$\\vdash \\texttt{(/lam : \\#B. (! 1))} : \
+                              \\texttt{(\\#B -\\textgreater{} \\#B)}$}"));
+    }
+}
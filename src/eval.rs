@@ -0,0 +1,463 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use slog::Logger;
+
+use core::Term;
+use errors::*;
+
+/// Why a term could not take another reduction step.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum StuckReason {
+    /// Reached `_|_`.
+    Bottom,
+    /// The condition of an `if` is a value other than `#T` or `#F`.
+    NonBoolCondition,
+    /// A value that is not a function was applied.
+    NonFunction,
+    /// A function was applied to the wrong number of arguments.
+    ArityMismatch { expected: usize, found: usize },
+    /// `!` was applied to something other than a boolean.
+    NonBoolNegation,
+    /// A variable index points past every enclosing binder.
+    UnboundVariable(u32),
+    /// Reached a typed hole that was never filled in.
+    Hole(String),
+}
+
+impl fmt::Display for StuckReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StuckReason::Bottom => write!(f, "reached bottom"),
+            StuckReason::NonBoolCondition => write!(f, "if condition is not a boolean"),
+            StuckReason::NonFunction => write!(f, "applied a non-function"),
+            StuckReason::ArityMismatch { expected, found } => {
+                write!(f, "expected {} argument(s), found {}", expected, found)
+            }
+            StuckReason::NonBoolNegation => write!(f, "negated a non-boolean"),
+            StuckReason::UnboundVariable(k) => write!(f, "variable {} is unbound", k),
+            StuckReason::Hole(ref x) => write!(f, "reached hole ?{}", x),
+        }
+    }
+}
+
+/// The reduction rule used by a single step.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rule {
+    Beta,
+    Not,
+    IfTrue,
+    IfFalse,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Rule::Beta => write!(f, "beta"),
+            Rule::Not => write!(f, "not"),
+            Rule::IfTrue => write!(f, "if-true"),
+            Rule::IfFalse => write!(f, "if-false"),
+        }
+    }
+}
+
+/// Result of trying to reduce a term by one step.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Step {
+    Value,
+    Reduced(Term, Rule),
+    /// Carries the subterm that could not be reduced.
+    Stuck(Term, StuckReason),
+}
+
+/// Result of running a term to completion.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Value(Term),
+    OutOfFuel { steps: u64, term: Term },
+    Timeout {
+        steps: u64,
+        elapsed: Duration,
+        term: Term,
+    },
+    Stuck {
+        steps: u64,
+        term: Term,
+        subterm: Term,
+        reason: StuckReason,
+    },
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Outcome::Value(ref v) => write!(f, "{}", v.unparse()),
+            Outcome::OutOfFuel { steps, ref term } => {
+                write!(f, "out of fuel after {} steps at {}", steps, term.unparse())
+            }
+            Outcome::Timeout { steps, elapsed, ref term } => {
+                write!(f,
+                       "timed out after {} steps ({}ms) at {}",
+                       steps,
+                       elapsed.as_secs() * 1000 + elapsed.subsec_nanos() as u64 / 1_000_000,
+                       term.unparse())
+            }
+            Outcome::Stuck { steps, ref subterm, ref reason, .. } => {
+                write!(f,
+                       "stuck after {} steps at {} because {}",
+                       steps,
+                       subterm.unparse(),
+                       reason)
+            }
+        }
+    }
+}
+
+/// Adds `d` to every variable in `t` that points past `cutoff` binders.
+pub fn shift(t: &Term, d: i64, cutoff: u32) -> Term {
+    match *t {
+        Term::Var(k) if k > cutoff => Term::Var((k as i64 + d) as u32),
+        Term::Abs(ref tys, ref body) => {
+            Term::abs(tys, shift(body, d, cutoff + tys.len() as u32))
+        }
+        Term::App(ref f, ref args) => {
+            Term::App(Box::new(shift(f, d, cutoff)),
+                      args.iter().map(|a| shift(a, d, cutoff)).collect())
+        }
+        Term::If(ref c, ref b1, ref b2) => {
+            Term::if_(shift(c, d, cutoff), shift(b1, d, cutoff), shift(b2, d, cutoff))
+        }
+        ref t => t.clone(),
+    }
+}
+
+/// Substitutes `args` for the innermost `args.len()` binders of `body`.
+pub fn beta(body: &Term, args: &[Term]) -> Term {
+    fn go(t: &Term, args: &[Term], depth: u32) -> Term {
+        let n = args.len() as u32;
+
+        match *t {
+            Term::Var(k) if k <= depth => Term::Var(k),
+            Term::Var(k) if k <= depth + n => {
+                shift(&args[(n - (k - depth)) as usize], depth as i64, 0)
+            }
+            Term::Var(k) => Term::Var(k - n),
+            Term::Abs(ref tys, ref body) => Term::abs(tys, go(body, args, depth + tys.len() as u32)),
+            Term::App(ref f, ref xs) => {
+                Term::App(Box::new(go(f, args, depth)),
+                          xs.iter().map(|x| go(x, args, depth)).collect())
+            }
+            Term::If(ref c, ref b1, ref b2) => {
+                Term::if_(go(c, args, depth), go(b1, args, depth), go(b2, args, depth))
+            }
+            ref t => t.clone(),
+        }
+    }
+
+    go(body, args, 0)
+}
+
+/// Performs one call-by-value, left-to-right reduction step.
+pub fn step(t: &Term) -> Step {
+    if t.is_val() {
+        return Step::Value;
+    }
+
+    match *t {
+        Term::If(ref cond, ref b1, ref b2) => {
+            match **cond {
+                Term::True => Step::Reduced(b1.as_ref().clone(), Rule::IfTrue),
+                Term::False => Step::Reduced(b2.as_ref().clone(), Rule::IfFalse),
+                ref c if c.is_val() => Step::Stuck(t.clone(), StuckReason::NonBoolCondition),
+                ref c => {
+                    match step(c) {
+                        Step::Reduced(c, rule) => {
+                            Step::Reduced(Term::if_(c, b1.as_ref().clone(), b2.as_ref().clone()),
+                                          rule)
+                        }
+                        s => s,
+                    }
+                }
+            }
+        }
+        Term::App(ref f, ref args) => {
+            if !f.is_val() {
+                return match step(f) {
+                    Step::Reduced(f, rule) => Step::Reduced(Term::app(f, args), rule),
+                    s => s,
+                };
+            }
+
+            if let Some(i) = args.iter().position(|a| !a.is_val()) {
+                return match step(&args[i]) {
+                    Step::Reduced(a, rule) => {
+                        let mut args = args.clone();
+                        args[i] = a;
+                        Step::Reduced(Term::App(f.clone(), args), rule)
+                    }
+                    s => s,
+                };
+            }
+
+            apply(t, f, args)
+        }
+        Term::Stuck => Step::Stuck(Term::Stuck, StuckReason::Bottom),
+        Term::Hole(ref x) => Step::Stuck(t.clone(), StuckReason::Hole(x.clone())),
+        _ => Step::Value,
+    }
+}
+
+/// Path to the subterm the next `step` reduces or gets stuck on, or `None` if
+/// `t` is a value. Child `0` of an application is the function and `i + 1`
+/// its `i`-th argument; the children of an `if` are numbered in order.
+pub fn redex(t: &Term) -> Option<Vec<usize>> {
+    if t.is_val() {
+        return None;
+    }
+
+    let (i, sub) = match *t {
+        Term::If(ref cond, ..) if !cond.is_val() => (0, cond.as_ref()),
+        Term::App(ref f, _) if !f.is_val() => (0, f.as_ref()),
+        Term::App(_, ref args) => {
+            match args.iter().position(|a| !a.is_val()) {
+                Some(i) => (i + 1, &args[i]),
+                None => return Some(Vec::new()),
+            }
+        }
+        _ => return Some(Vec::new()),
+    };
+
+    let mut path = redex(sub).unwrap_or_default();
+    path.insert(0, i);
+    Some(path)
+}
+
+fn apply(t: &Term, f: &Term, args: &[Term]) -> Step {
+    match *f {
+        Term::Abs(ref tys, ref body) => {
+            if tys.len() != args.len() {
+                let reason = StuckReason::ArityMismatch {
+                    expected: tys.len(),
+                    found: args.len(),
+                };
+                Step::Stuck(t.clone(), reason)
+            } else {
+                Step::Reduced(beta(body, args), Rule::Beta)
+            }
+        }
+        Term::Not => {
+            if args.len() != 1 {
+                let reason = StuckReason::ArityMismatch {
+                    expected: 1,
+                    found: args.len(),
+                };
+                return Step::Stuck(t.clone(), reason);
+            }
+
+            match args[0] {
+                Term::True => Step::Reduced(Term::False, Rule::Not),
+                Term::False => Step::Reduced(Term::True, Rule::Not),
+                _ => Step::Stuck(t.clone(), StuckReason::NonBoolNegation),
+            }
+        }
+        _ => Step::Stuck(t.clone(), StuckReason::NonFunction),
+    }
+}
+
+pub struct Evaluator {
+    logger: Logger,
+    fuel: Option<u64>,
+    timeout: Option<Duration>,
+}
+
+impl Evaluator {
+    pub fn new(logger: &Logger) -> Evaluator {
+        Evaluator {
+            logger: logger.new(o!("phase" => "Evaluation")),
+            fuel: None,
+            timeout: None,
+        }
+    }
+
+    /// Limits evaluation to `fuel` reduction steps; `None` means unlimited.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Limits evaluation to `timeout` of wall-clock time; `None` means unlimited.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    pub fn run(&mut self, t: &Term) -> Outcome {
+        let start = Instant::now();
+        let mut steps = 0;
+        let mut term = t.clone();
+
+        loop {
+            if term.is_val() {
+                return Outcome::Value(term);
+            }
+
+            if self.fuel.map_or(false, |fuel| steps >= fuel) {
+                return Outcome::OutOfFuel {
+                    steps: steps,
+                    term: term,
+                };
+            }
+
+            if let Some(timeout) = self.timeout {
+                let elapsed = start.elapsed();
+                if elapsed >= timeout {
+                    return Outcome::Timeout {
+                        steps: steps,
+                        elapsed: elapsed,
+                        term: term,
+                    };
+                }
+            }
+
+            trace!(self.logger, "step"; "n" => steps, "term" => term.unparse());
+
+            match step(&term) {
+                Step::Value => return Outcome::Value(term),
+                Step::Reduced(next, _) => {
+                    term = next;
+                    steps += 1;
+                }
+                Step::Stuck(subterm, reason) => {
+                    return Outcome::Stuck {
+                        steps: steps,
+                        term: term,
+                        subterm: subterm,
+                        reason: reason,
+                    };
+                }
+            }
+        }
+    }
+
+    /// Runs `t` to a value. Programs with holes are refused up front.
+    pub fn eval(&mut self, t: &Term) -> Result<Term> {
+        let holes = t.holes();
+        if !holes.is_empty() {
+            bail!("Cannot evaluate a program with unfilled holes: ?{}", holes.join(", ?"));
+        }
+
+        match self.run(t) {
+            Outcome::Value(v) => Ok(v),
+            outcome => Err(outcome.to_string().into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    use slog::{Discard, Logger};
+
+    use core::Term;
+    use debrujin::RenameVisitor;
+    use lispy;
+
+    fn get(s: &str) -> Term {
+        let tl = lispy::get_code(s.as_bytes()).unwrap();
+
+        let mut rv = RenameVisitor::new();
+        rv.rename_term(&tl).unwrap()
+    }
+
+    fn evaluator() -> Evaluator {
+        Evaluator::new(&Logger::root(Discard, o!()))
+    }
+
+    #[test]
+    fn test_eval() {
+        let mut ev = evaluator();
+
+        assert_eq!(ev.eval(&get("#T")).unwrap(), Term::True);
+        assert_eq!(ev.eval(&get("(! #T)")).unwrap(), Term::False);
+        assert_eq!(ev.eval(&get("((/lam x: #B.x) #T)")).unwrap(), Term::True);
+        assert_eq!(ev.eval(&get("(if (! #T) 1 2)")).unwrap(), Term::Int(2));
+        assert_eq!(ev.eval(&get("((/lam test: #B, val: #Int. (if test val -2000)) #T 2000)"))
+                       .unwrap(),
+                   Term::Int(2000));
+        // K combinator
+        assert_eq!(ev.eval(&get("(((/lam x: #Int. (/lam y: #B. x)) 1) #F)")).unwrap(),
+                   Term::Int(1));
+        assert_eq!(ev.eval(&get("((/lam f: (#B -> #B). (f #F)) !)")).unwrap(),
+                   Term::True);
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut ev = evaluator();
+        let t = get("(! (! (! #T)))");
+
+        ev.set_fuel(Some(2));
+        match ev.run(&t) {
+            Outcome::OutOfFuel { steps, term } => {
+                assert_eq!(steps, 2);
+                assert_eq!(term, get("(! #T)"));
+            }
+            o => panic!("unexpected outcome {:?}", o),
+        }
+
+        ev.set_fuel(Some(3));
+        assert_eq!(ev.run(&t), Outcome::Value(Term::False));
+
+        ev.set_fuel(None);
+        ev.set_timeout(Some(Duration::from_secs(0)));
+        match ev.run(&t) {
+            Outcome::Timeout { steps, .. } => assert_eq!(steps, 0),
+            o => panic!("unexpected outcome {:?}", o),
+        }
+    }
+
+    #[test]
+    fn test_stuck() {
+        let mut ev = evaluator();
+
+        match ev.run(&get("(if 1 #T #F)")) {
+            Outcome::Stuck { subterm, reason, .. } => {
+                assert_eq!(subterm, get("(if 1 #T #F)"));
+                assert_eq!(reason, StuckReason::NonBoolCondition);
+            }
+            o => panic!("unexpected outcome {:?}", o),
+        }
+
+        match ev.run(&get("((/lam x: #B. (x #T)) #F)")) {
+            Outcome::Stuck { steps, subterm, reason, .. } => {
+                assert_eq!(steps, 1);
+                assert_eq!(subterm, Term::app(Term::False, &[Term::True]));
+                assert_eq!(reason, StuckReason::NonFunction);
+            }
+            o => panic!("unexpected outcome {:?}", o),
+        }
+
+        match ev.run(&get("((/lam x: #B, y: #B. x) #T)")) {
+            Outcome::Stuck { reason, .. } => {
+                assert_eq!(reason,
+                           StuckReason::ArityMismatch {
+                               expected: 2,
+                               found: 1,
+                           })
+            }
+            o => panic!("unexpected outcome {:?}", o),
+        }
+
+        assert!(ev.eval(&get("(! 3)")).is_err());
+
+        match ev.run(&get("((/lam x: #B. (! ?h)) #T)")) {
+            Outcome::Stuck { subterm, reason, .. } => {
+                assert_eq!(subterm, Term::Hole("h".into()));
+                assert_eq!(reason, StuckReason::Hole("h".into()));
+            }
+            o => panic!("unexpected outcome {:?}", o),
+        }
+        let e = ev.eval(&get("(if ?c ?a #T)")).unwrap_err();
+        assert_eq!(e.to_string(), "Cannot evaluate a program with unfilled holes: ?c, ?a");
+    }
+}
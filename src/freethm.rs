@@ -0,0 +1,590 @@
+//! Free theorems (Wadler, "Theorems for free!") of polymorphic types.
+//!
+//! Neither terms nor `core::Ty` have type variables, so polymorphic types
+//! live here as schemes `forall X Y. T`. The theorem of a scheme is the
+//! relational interpretation of `T`, with the relation for each variable `X`
+//! taken to be the graph of a function `fX : X -> X'`.
+//!
+//! A polymorphic term is written as a template: a program whose annotations
+//! use the scheme's variables, such as `(/lam x: X. x)`. `check` instantiates
+//! it with `#B` and `#Int` and tests the theorem with the evaluator. Only
+//! first-order schemes, whose arguments are not functions, can be tested,
+//! since related function arguments cannot be sampled at random.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use slog::Logger;
+
+use core::{Term, Ty, TyLit};
+use debrujin::{name_term, RenameVisitor};
+use errors::*;
+use eval::Evaluator;
+use gen::Generator;
+use lispy::{scan, tokenize};
+use lispy::tokens::Token;
+use span::{self, Spans};
+use typecheck::TypeCheckVisitor;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PolyTy {
+    Var(String),
+    Base(TyLit),
+    Arrow(Vec<PolyTy>, Box<PolyTy>),
+}
+
+/// The two instances a relation connects. Variables on the right are primed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl PolyTy {
+    /// Replaces every variable by its type in `sub`, which must have them all.
+    pub fn instantiate(&self, sub: &HashMap<String, Ty>) -> Ty {
+        match *self {
+            PolyTy::Var(ref x) => sub[x].clone(),
+            PolyTy::Base(lit) => lit.into(),
+            PolyTy::Arrow(ref params, ref res) => {
+                let params: Vec<Ty> = params.iter().map(|p| p.instantiate(sub)).collect();
+                Ty::arrow(&params, res.instantiate(sub))
+            }
+        }
+    }
+
+    /// The type read as a proposition, like `Ty::to_prop`.
+    pub fn to_prop(&self) -> String {
+        match *self {
+            PolyTy::Var(ref x) => x.clone(),
+            PolyTy::Base(lit) => Ty::Base(lit).to_prop(),
+            PolyTy::Arrow(ref params, ref res) => {
+                let params: Vec<String> = params.iter()
+                    .map(|p| match *p {
+                        PolyTy::Arrow(..) => format!("({})", p.to_prop()),
+                        _ => p.to_prop(),
+                    })
+                    .collect();
+                format!("{} → {}", params.join(" ∧ "), res.to_prop())
+            }
+        }
+    }
+
+    fn show(&self, side: Side) -> String {
+        match *self {
+            PolyTy::Var(ref x) if side == Side::Right => format!("{}'", x),
+            PolyTy::Var(ref x) => x.clone(),
+            PolyTy::Base(lit) => lit.to_lit().into(),
+            PolyTy::Arrow(ref params, ref res) => {
+                let params: Vec<String> = params.iter().map(|p| p.show(side)).collect();
+                format!("({} -> {})", params.join(", "), res.show(side))
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Scheme {
+    pub vars: Vec<String>,
+    pub ty: PolyTy,
+}
+
+impl Scheme {
+    /// Parses `forall X Y. T` (or `∀X Y. T`), where `T` is written like a
+    /// type annotation that may also mention the variables.
+    pub fn parse(src: &str) -> Result<Scheme> {
+        let src = src.replace('∀', "forall ");
+        let lex = tokenize::scan(src.as_bytes())?;
+        let mut tokens = lex.tokens.into_iter().peekable();
+
+        let mut vars = Vec::new();
+        if tokens.peek() == Some(&Token::Id("forall".into())) {
+            tokens.next();
+            loop {
+                match tokens.next() {
+                    Some(Token::Id(x)) => vars.push(x),
+                    Some(Token::Dot) => break,
+                    t => {
+                        bail!("Expected a type variable or . after forall, found {:?}",
+                              t.map(|t| t.unparse()))
+                    }
+                }
+            }
+        }
+
+        let ty = parse_poly(&mut tokens, &vars)?;
+        if let Some(t) = tokens.next() {
+            bail!("Unexpected {} after the type", t.unparse());
+        }
+
+        Ok(Scheme {
+               vars: vars,
+               ty: ty,
+           })
+    }
+
+    /// The scheme read as a proposition, its variables universally quantified.
+    pub fn to_prop(&self) -> String {
+        if self.vars.is_empty() {
+            self.ty.to_prop()
+        } else {
+            format!("∀{}. {}", self.vars.join(" "), self.ty.to_prop())
+        }
+    }
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.vars.is_empty() {
+            write!(f, "forall {}. ", self.vars.join(" "))?;
+        }
+        write!(f, "{}", self.ty.show(Side::Left))
+    }
+}
+
+fn parse_poly<I>(tokens: &mut I, vars: &[String]) -> Result<PolyTy>
+    where I: Iterator<Item = Token>
+{
+    match tokens.next() {
+        Some(Token::TypeLit(lit)) => Ok(PolyTy::Base(lit)),
+        Some(Token::Id(ref x)) if vars.contains(x) => Ok(PolyTy::Var(x.clone())),
+        Some(Token::LParen) => {
+            let mut params = vec![parse_poly(tokens, vars)?];
+            loop {
+                match tokens.next() {
+                    Some(Token::Comma) => params.push(parse_poly(tokens, vars)?),
+                    Some(Token::Arrow) => break,
+                    t => bail!("Expected , or -> in a type, found {:?}", t.map(|t| t.unparse())),
+                }
+            }
+            let res = parse_poly(tokens, vars)?;
+            match tokens.next() {
+                Some(Token::RParen) => Ok(PolyTy::Arrow(params, Box::new(res))),
+                t => bail!("Expected ) after a type, found {:?}", t.map(|t| t.unparse())),
+            }
+        }
+        Some(Token::Id(x)) => bail!("Unknown type variable {}", x),
+        t => bail!("Expected a type, found {:?}", t.map(|t| t.unparse())),
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Expr {
+    /// The polymorphic term, `g`, at the types of one side.
+    Poly(Side),
+    /// `fX`, the function relating `X` to `X'`.
+    Fun(String),
+    Var(String),
+    App(Box<Expr>, Vec<Expr>),
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expr::Poly(_) => write!(f, "g"),
+            Expr::Fun(ref x) => write!(f, "f{}", x),
+            Expr::Var(ref x) => write!(f, "{}", x),
+            Expr::App(ref fun, ref args) => {
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                write!(f, "({} {})", fun, args.join(" "))
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Prop {
+    Eq(Expr, Expr),
+    /// Quantifies over values of each type, instantiated on the given side.
+    Forall(Vec<(String, PolyTy, Side)>, Box<Prop>),
+    Implies(Vec<Prop>, Box<Prop>),
+}
+
+impl fmt::Display for Prop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Prop::Eq(ref a, ref b) => write!(f, "{} = {}", a, b),
+            Prop::Forall(ref binders, ref body) => {
+                let binders: Vec<String> = binders.iter()
+                    .map(|&(ref x, ref ty, side)| format!("{} : {}", x, ty.show(side)))
+                    .collect();
+                write!(f, "for all {}. {}", binders.join(", "), body)
+            }
+            Prop::Implies(ref hyps, ref concl) => {
+                let hyps: Vec<String> = hyps.iter().map(|h| format!("({})", h)).collect();
+                write!(f, "if {} then {}", hyps.join(" and "), concl)
+            }
+        }
+    }
+}
+
+/// Builds the relation of a type between two expressions.
+struct Relator {
+    vars: usize,
+    funs: usize,
+}
+
+impl Relator {
+    fn fresh(&mut self, fun: bool) -> String {
+        let (names, n) = if fun {
+            self.funs += 1;
+            (["h", "k"].as_ref(), self.funs - 1)
+        } else {
+            self.vars += 1;
+            (["x", "y", "z", "u", "v", "w"].as_ref(), self.vars - 1)
+        };
+
+        match n / names.len() {
+            0 => names[n % names.len()].into(),
+            i => format!("{}{}", names[n % names.len()], i),
+        }
+    }
+
+    fn rel(&mut self, ty: &PolyTy, a: Expr, b: Expr) -> Prop {
+        let (params, res) = match *ty {
+            PolyTy::Base(_) => return Prop::Eq(a, b),
+            PolyTy::Var(ref x) => return Prop::Eq(Expr::App(Box::new(Expr::Fun(x.clone())), vec![a]), b),
+            PolyTy::Arrow(ref params, ref res) => (params, res),
+        };
+
+        let mut binders = Vec::new();
+        let mut hyps = Vec::new();
+        let (mut xs, mut ys) = (Vec::new(), Vec::new());
+
+        // Related arguments of base or variable type are determined by the
+        // left one, so only that one is quantified over.
+        for p in params {
+            match *p {
+                PolyTy::Base(_) => {
+                    let x = self.fresh(false);
+                    binders.push((x.clone(), p.clone(), Side::Left));
+                    xs.push(Expr::Var(x.clone()));
+                    ys.push(Expr::Var(x));
+                }
+                PolyTy::Var(ref v) => {
+                    let x = self.fresh(false);
+                    binders.push((x.clone(), p.clone(), Side::Left));
+                    xs.push(Expr::Var(x.clone()));
+                    ys.push(Expr::App(Box::new(Expr::Fun(v.clone())), vec![Expr::Var(x)]));
+                }
+                PolyTy::Arrow(..) => {
+                    let h = self.fresh(true);
+                    let h_ = format!("{}'", h);
+                    binders.push((h.clone(), p.clone(), Side::Left));
+                    binders.push((h_.clone(), p.clone(), Side::Right));
+                    hyps.push(self.rel(p, Expr::Var(h.clone()), Expr::Var(h_.clone())));
+                    xs.push(Expr::Var(h));
+                    ys.push(Expr::Var(h_));
+                }
+            }
+        }
+
+        let concl = self.rel(res, Expr::App(Box::new(a), xs), Expr::App(Box::new(b), ys));
+        let body = if hyps.is_empty() {
+            concl
+        } else {
+            Prop::Implies(hyps, Box::new(concl))
+        };
+        Prop::Forall(binders, Box::new(body))
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Theorem {
+    pub scheme: Scheme,
+    /// About `g`, any term of the scheme's type.
+    pub prop: Prop,
+}
+
+impl fmt::Display for Theorem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.scheme.vars.is_empty() {
+            return write!(f, "{}", self.prop);
+        }
+
+        let types: Vec<String> = self.scheme.vars.iter().map(|x| format!("{}, {}'", x, x)).collect();
+        let funs: Vec<String> = self.scheme
+            .vars
+            .iter()
+            .map(|x| format!("f{} : {} -> {}'", x, x, x))
+            .collect();
+        write!(f,
+               "for all types {} and functions {},\n  {}",
+               types.join(", "),
+               funs.join(", "),
+               self.prop)
+    }
+}
+
+/// The free theorem of `scheme`.
+pub fn theorem(scheme: &Scheme) -> Theorem {
+    let mut r = Relator { vars: 0, funs: 0 };
+
+    Theorem {
+        scheme: scheme.clone(),
+        prop: r.rel(&scheme.ty, Expr::Poly(Side::Left), Expr::Poly(Side::Right)),
+    }
+}
+
+/// An instance on which the two sides of a free theorem differ.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Counterexample {
+    /// Each variable with its left and right type and the function between them.
+    pub instances: Vec<(String, Ty, Ty, Term)>,
+    /// The values chosen for the quantified variables.
+    pub values: Vec<(String, Term)>,
+    pub lhs: Term,
+    pub rhs: Term,
+}
+
+fn show_term(t: &Term) -> String {
+    name_term(t).map(|t| t.pretty()).unwrap_or_else(|_| t.unparse())
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts: Vec<String> = self.instances
+            .iter()
+            .map(|&(ref x, ref l, ref r, ref fun)| {
+                format!("{} = {}, {}' = {}, f{} = {}", x, l, x, r, x, show_term(fun))
+            })
+            .collect();
+        parts.extend(self.values.iter().map(|&(ref x, ref v)| format!("{} = {}", x, show_term(v))));
+
+        write!(f,
+               "{}: {} is not {}",
+               parts.join(", "),
+               show_term(&self.lhs),
+               show_term(&self.rhs))
+    }
+}
+
+/// Whether `p` mentions a function-typed variable, which `check` cannot sample.
+fn higher_order(p: &Prop) -> bool {
+    match *p {
+        Prop::Eq(..) => false,
+        Prop::Implies(..) => true,
+        Prop::Forall(_, ref body) => higher_order(body),
+    }
+}
+
+/// Instantiates the variables in the annotations of `template`.
+fn instantiate_template(template: &str, sub: &HashMap<String, Ty>) -> Result<Term> {
+    let lex = tokenize::scan(template.as_bytes())?;
+    let code = scan::parse_with_type_vars(lex, sub)?;
+    span::check_closed(&code, &Spans::new())?;
+
+    RenameVisitor::new().rename_term(&code)
+}
+
+struct Instance<'a> {
+    g: [Term; 2],
+    funs: HashMap<String, Term>,
+    subs: [HashMap<String, Ty>; 2],
+    values: Vec<(String, Term)>,
+    gen: &'a mut Generator,
+    evaluator: Evaluator,
+}
+
+impl<'a> Instance<'a> {
+    fn side(&self, side: Side) -> usize {
+        if side == Side::Left { 0 } else { 1 }
+    }
+
+    fn term(&self, e: &Expr) -> Term {
+        match *e {
+            Expr::Poly(side) => self.g[self.side(side)].clone(),
+            Expr::Fun(ref x) => self.funs[x].clone(),
+            Expr::Var(ref x) => self.values.iter().rev().find(|v| v.0 == *x).unwrap().1.clone(),
+            Expr::App(ref f, ref args) => {
+                Term::App(Box::new(self.term(f)), args.iter().map(|a| self.term(a)).collect())
+            }
+        }
+    }
+
+    /// The two sides of the first equation of `p` that fails, if any.
+    fn holds(&mut self, p: &Prop) -> Result<Option<(Term, Term)>> {
+        match *p {
+            Prop::Eq(ref a, ref b) => {
+                let a = self.evaluator.eval(&self.term(a))?;
+                let b = self.evaluator.eval(&self.term(b))?;
+                Ok(if a == b { None } else { Some((a, b)) })
+            }
+            Prop::Forall(ref binders, ref body) => {
+                for &(ref x, ref ty, side) in binders {
+                    let ty = ty.instantiate(&self.subs[self.side(side)]);
+                    let v = self.gen.term(&ty, 4);
+                    let v = self.evaluator.eval(&v)?;
+                    self.values.push((x.clone(), v));
+                }
+                self.holds(body)
+            }
+            Prop::Implies(..) => bail!("Cannot sample related functions"),
+        }
+    }
+}
+
+/// Tests the free theorem of `scheme` on `runs` random instances of
+/// `template`, each variable instantiated with `#B` or `#Int` on either side.
+/// Returns the first instance that fails.
+pub fn check(scheme: &Scheme,
+             template: &str,
+             runs: usize,
+             seed: u64,
+             logger: &Logger)
+             -> Result<Option<Counterexample>> {
+    let thm = theorem(scheme);
+    if higher_order(&thm.prop) {
+        bail!("Cannot test the theorem of {}, it quantifies over functions", scheme);
+    }
+
+    // Each variable is #B or #Int, so there are few distinct instantiations;
+    // parse and typecheck each of them once.
+    let mut instantiated: HashMap<Vec<Ty>, Term> = HashMap::new();
+
+    let mut gen = Generator::new(seed);
+    for _ in 0..runs {
+        let mut subs = [HashMap::new(), HashMap::new()];
+        let mut funs = HashMap::new();
+        let mut instances = Vec::new();
+        for x in &scheme.vars {
+            let (l, r) = (gen.ty(0), gen.ty(0));
+            let f = gen.term(&Ty::Arrow(vec![l.clone()], Box::new(r.clone())), 6);
+            subs[0].insert(x.clone(), l.clone());
+            subs[1].insert(x.clone(), r.clone());
+            funs.insert(x.clone(), f.clone());
+            instances.push((x.clone(), l, r, f));
+        }
+
+        let mut g = Vec::new();
+        for sub in &subs {
+            let key: Vec<Ty> = scheme.vars.iter().map(|x| sub[x].clone()).collect();
+            if !instantiated.contains_key(&key) {
+                let t = instantiate_template(template, sub)?;
+                let ty = TypeCheckVisitor::new().type_of(&t)?;
+                if ty != scheme.ty.instantiate(sub) {
+                    bail!("{} has type {} instead of an instance of {}", template, ty, scheme);
+                }
+                instantiated.insert(key.clone(), t);
+            }
+            g.push(instantiated[&key].clone());
+        }
+
+        let mut inst = Instance {
+            g: [g[0].clone(), g[1].clone()],
+            funs: funs,
+            subs: subs,
+            values: Vec::new(),
+            gen: &mut gen,
+            evaluator: Evaluator::new(logger),
+        };
+        if let Some((lhs, rhs)) = inst.holds(&thm.prop)? {
+            return Ok(Some(Counterexample {
+                               instances: instances,
+                               values: inst.values,
+                               lhs: lhs,
+                               rhs: rhs,
+                           }));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lispy;
+    use slog::Discard;
+
+    fn thm(s: &str) -> String {
+        theorem(&Scheme::parse(s).unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_theorem() {
+        assert_eq!(thm("forall X. (X -> X)"),
+                   "for all types X, X' and functions fX : X -> X',\n  for all x : X. (fX (g x)) = \
+                    (g (fX x))");
+        assert_eq!(thm("∀X Y. (X, Y -> X)"),
+                   "for all types X, X', Y, Y' and functions fX : X -> X', fY : Y -> Y',\n  for \
+                    all x : X, y : Y. (fX (g x y)) = (g (fX x) (fY y))");
+        assert_eq!(thm("forall X. ((X -> #B), X -> #B)"),
+                   "for all types X, X' and functions fX : X -> X',\n  for all h : (X -> #B), \
+                    h' : (X' -> #B), y : X. if (for all x : X. (h x) = (h' (fX x))) then (g h \
+                    y) = (g h' (fX y))");
+        assert_eq!(thm("(#B -> #Int)"), "for all x : #B. (g x) = (g x)");
+
+        assert_eq!(Scheme::parse("forall X. (X -> (#Int -> X))").unwrap().to_string(),
+                   "forall X. (X -> (#Int -> X))");
+        assert!(Scheme::parse("forall X. (Y -> X)").is_err());
+        assert_eq!(Scheme::parse("forall X (X -> X)").unwrap_err().to_string(),
+                   "Expected a type variable or . after forall, found Some(\"(\")");
+
+        assert_eq!(Scheme::parse("∀X Y. ((X -> Y), X -> (#B -> Y))").unwrap().to_prop(),
+                   "∀X Y. (X → Y) ∧ X → Bool → Y");
+        assert!(Scheme::parse("forall X. (X -> X) #B").is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let logger = Logger::root(Discard, o!());
+        let check = |s: &str, t: &str| check(&Scheme::parse(s).unwrap(), t, 50, 1, &logger);
+
+        assert_eq!(check("forall X. (X -> X)", "(/lam x: X. x)").unwrap(), None);
+        assert_eq!(check("forall X Y. (X -> (Y -> X))", "(/lam x: X. (/lam y: Y. x))").unwrap(),
+                   None);
+        assert_eq!(check("forall X. (#B, X, X -> X)", "(/lam b: #B, x: X, y: X. (if b y x))")
+                       .unwrap(),
+                   None);
+
+        // Only annotations are instantiated, so term variables may share a
+        // name with a type variable.
+        assert_eq!(check("forall X. (X -> X)", "(/lam X: X. X)").unwrap(), None);
+        assert_eq!(check("(#B -> #B)", "(/lam b: #B. b)").unwrap(), None);
+
+        assert!(check("forall X. (X -> X)", "(/lam x: X. 1)").is_err());
+        assert!(check("forall X. (X -> X)", "(/lam x: X. y)").is_err());
+        assert!(check("forall X. (X, X -> X)", "(/lam x: X, x: X. x)").is_err());
+        assert!(check("forall X. ((X -> X), X -> X)", "(/lam f: (X -> X), x: X. (f x))").is_err());
+    }
+
+    #[test]
+    fn test_counterexample() {
+        let logger = Logger::root(Discard, o!());
+        let get = |s: &str| {
+            RenameVisitor::new().rename_term(&lispy::get_code(s.as_bytes()).unwrap()).unwrap()
+        };
+        let (b, i): (Ty, Ty) = (TyLit::Bool.into(), TyLit::Int.into());
+
+        // No template fails, so compare a pair of instances that do not come
+        // from one polymorphic term: the identity on the left, a constant on
+        // the right.
+        let scheme = Scheme::parse("forall X. (X -> X)").unwrap();
+        let f = get("(/lam b: #B. (if b 1 2))");
+        let mut gen = Generator::new(3);
+        let mut inst = Instance {
+            g: [get("(/lam x: #B. x)"), get("(/lam x: #Int. 0)")],
+            funs: vec![("X".to_string(), f.clone())].into_iter().collect(),
+            subs: [vec![("X".to_string(), b.clone())].into_iter().collect(),
+                   vec![("X".to_string(), i.clone())].into_iter().collect()],
+            values: Vec::new(),
+            gen: &mut gen,
+            evaluator: Evaluator::new(&logger),
+        };
+
+        let (lhs, rhs) = inst.holds(&theorem(&scheme).prop).unwrap().unwrap();
+        let x = inst.values[0].1.clone();
+        assert_eq!(lhs, if x == Term::True { Term::Int(1) } else { Term::Int(2) });
+        assert_eq!(rhs, Term::Int(0));
+
+        let cex = Counterexample {
+            instances: vec![("X".into(), b, i, f)],
+            values: vec![("x".into(), Term::True)],
+            lhs: Term::Int(1),
+            rhs: Term::Int(0),
+        };
+        assert_eq!(cex.to_string(),
+                   "X = #B, X' = #Int, fX = (/lam x: #B. (if x 1 2)), x = #T: 1 is not 0");
+    }
+}
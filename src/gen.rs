@@ -0,0 +1,371 @@
+//! Random well-typed terms, shrinking, and a property check of type safety.
+//!
+//! `quickcheck` generates closed terms of random types and checks progress
+//! (a well-typed term is a value or can step) and preservation (a step keeps
+//! the type) against `eval::step` and `TypeCheckVisitor`. A failing term is
+//! shrunk to a smaller one that still fails before it is reported.
+
+use std::fmt;
+
+use core::{Term, Ty, TyLit};
+use eval::{self, Step, StuckReason};
+use opt;
+use typecheck::{Judgement, TypeCheckVisitor, TypeEnv};
+
+/// A small xorshift generator, so runs are reproducible from their seed.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // The state must never be zero.
+        Rng(seed ^ 0x9e37_79b9_7f4a_7c15 | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in `0..n`, `n` must be positive.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+pub struct Generator {
+    rng: Rng,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Generator {
+        Generator { rng: Rng::new(seed) }
+    }
+
+    /// A type of `#B`, `#Int` and arrows taking one or two parameters, with
+    /// arrows nested at most `depth` deep.
+    pub fn ty(&mut self, depth: usize) -> Ty {
+        if depth == 0 || self.rng.below(2) == 0 {
+            return if self.rng.below(2) == 0 { TyLit::Bool } else { TyLit::Int }.into();
+        }
+
+        let params: Vec<Ty> = (0..1 + self.rng.below(2)).map(|_| self.ty(depth - 1)).collect();
+        Ty::arrow(&params, self.ty(depth - 1))
+    }
+
+    /// A closed term of type `ty` with roughly `size` nodes. The only term of
+    /// type `Bottom` is `_|_`.
+    pub fn term(&mut self, ty: &Ty, size: usize) -> Term {
+        self.gen(ty, &mut TypeEnv::new(), size)
+    }
+
+    fn gen(&mut self, ty: &Ty, gamma: &mut TypeEnv, size: usize) -> Term {
+        if size <= 1 {
+            return self.leaf(ty, gamma);
+        }
+        let size = size - 1;
+
+        match (self.rng.below(4), ty) {
+            (0, &Ty::Arrow(..)) |
+            (1, &Ty::Arrow(..)) => self.abs(ty, gamma, size),
+            (0, &Ty::Base(TyLit::Bool)) => {
+                let arg = self.gen(ty, gamma, size);
+                Term::app(Term::Not, &[arg])
+            }
+            (0, _) | (1, _) => self.app(ty, gamma, size),
+            (2, _) => {
+                let c = self.gen(&TyLit::Bool.into(), gamma, size / 3);
+                let b1 = self.gen(ty, gamma, size / 3);
+                let b2 = self.gen(ty, gamma, size / 3);
+                Term::if_(c, b1, b2)
+            }
+            _ => self.leaf(ty, gamma),
+        }
+    }
+
+    fn abs(&mut self, ty: &Ty, gamma: &mut TypeEnv, size: usize) -> Term {
+        match *ty {
+            Ty::Arrow(ref params, ref res) => {
+                gamma.extend(params.iter().cloned());
+                let body = self.gen(res, gamma, size);
+                let len = gamma.len() - params.len();
+                gamma.truncate(len);
+                Term::abs(params, body)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Applies a function of a random type returning `ty`.
+    fn app(&mut self, ty: &Ty, gamma: &mut TypeEnv, size: usize) -> Term {
+        let params: Vec<Ty> = (0..1 + self.rng.below(2)).map(|_| self.ty(1)).collect();
+        let f = self.gen(&Ty::arrow(&params, ty.clone()), gamma, size / 2);
+        let each = (size - size / 2) / params.len();
+        let args: Vec<Term> = params.iter().map(|p| self.gen(p, gamma, each)).collect();
+
+        Term::app(f, &args)
+    }
+
+    /// A variable or constant of type `ty`, or a lambda if there is none.
+    fn leaf(&mut self, ty: &Ty, gamma: &mut TypeEnv) -> Term {
+        let mut leaves: Vec<Term> = gamma.iter()
+            .enumerate()
+            .filter(|&(_, vty)| vty == ty)
+            .map(|(i, _)| Term::Var((gamma.len() - i) as u32))
+            .collect();
+
+        match *ty {
+            Ty::Base(TyLit::Bool) => leaves.extend(vec![Term::True, Term::False]),
+            Ty::Base(TyLit::Int) => leaves.push(Term::Int(self.rng.below(21) as i64 - 10)),
+            Ty::Bottom => leaves.push(Term::Stuck),
+            Ty::Arrow(ref params, ref res) => {
+                if params.len() == 1 && params[0] == TyLit::Bool.into() && **res == params[0] {
+                    leaves.push(Term::Not);
+                }
+                if leaves.is_empty() {
+                    return self.abs(ty, gamma, 1);
+                }
+            }
+        }
+
+        let i = self.rng.below(leaves.len());
+        leaves.swap_remove(i)
+    }
+}
+
+/// Smaller variants of `t`, not all of them well-typed.
+fn candidates(t: &Term) -> Vec<Term> {
+    let mut out = Vec::new();
+
+    match *t {
+        Term::Int(0) => {}
+        Term::Int(n) => {
+            out.push(Term::Int(0));
+            out.push(Term::Int(n / 2));
+        }
+        Term::Abs(..) | Term::App(..) | Term::If(..) => {
+            out.extend(vec![Term::True, Term::False, Term::Int(0), Term::Not]);
+        }
+        _ => {}
+    }
+
+    match *t {
+        Term::If(ref c, ref b1, ref b2) => {
+            out.push((**b1).clone());
+            out.push((**b2).clone());
+            out.extend(candidates(c).into_iter().map(|c| Term::If(c.into(), b1.clone(), b2.clone())));
+            out.extend(candidates(b1).into_iter().map(|b| Term::If(c.clone(), b.into(), b2.clone())));
+            out.extend(candidates(b2).into_iter().map(|b| Term::If(c.clone(), b1.clone(), b.into())));
+        }
+        Term::App(ref f, ref args) => {
+            if let Term::Abs(_, ref body) = **f {
+                out.push(eval::beta(body, args));
+            }
+            out.extend(candidates(f).into_iter().map(|f| Term::App(f.into(), args.clone())));
+            for (i, a) in args.iter().enumerate() {
+                for a in candidates(a) {
+                    let mut args = args.clone();
+                    args[i] = a;
+                    out.push(Term::App(f.clone(), args));
+                }
+            }
+        }
+        Term::Abs(ref tys, ref body) => {
+            out.extend(candidates(body).into_iter().map(|b| Term::abs(tys, b)));
+        }
+        _ => {}
+    }
+
+    out
+}
+
+/// What shrinking decreases: the number of nodes, then the size of the
+/// integer literals.
+fn weight(t: &Term) -> (usize, u64) {
+    fn ints(t: &Term) -> u64 {
+        match *t {
+            Term::Int(n) => n.abs() as u64,
+            Term::Abs(_, ref body) => ints(body),
+            Term::App(ref f, ref args) => ints(f) + args.iter().map(ints).sum::<u64>(),
+            Term::If(ref c, ref b1, ref b2) => ints(c) + ints(b1) + ints(b2),
+            _ => 0,
+        }
+    }
+
+    (opt::size(t), ints(t))
+}
+
+/// Closed terms smaller than `t` with the same type, most drastic first.
+pub fn shrink(t: &Term) -> Vec<Term> {
+    let mut tc = TypeCheckVisitor::new();
+    let ty = match tc.type_of(t) {
+        Ok(ty) => ty,
+        Err(_) => return Vec::new(),
+    };
+    let weight_t = weight(t);
+
+    candidates(t)
+        .into_iter()
+        .filter(|c| weight(c) < weight_t && tc.type_of(c).as_ref() == Ok(&ty))
+        .collect()
+}
+
+/// Shrinks `t` for as long as some smaller term still `fails`.
+pub fn minimize<F>(t: &Term, fails: F) -> Term
+    where F: Fn(&Term) -> bool
+{
+    let mut t = t.clone();
+    while let Some(smaller) = shrink(&t).into_iter().find(|c| fails(c)) {
+        t = smaller;
+    }
+    t
+}
+
+/// How a well-typed term broke type safety.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Violation {
+    /// `term` is neither a value nor can it step.
+    Progress { term: Term, reason: StuckReason },
+    /// `from` has type `ty` but stepped to `to`, which does not.
+    Preservation {
+        from: Term,
+        to: Term,
+        ty: Ty,
+        found: Judgement,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Violation::Progress { ref term, ref reason } => {
+                write!(f, "no progress: {} is stuck because {}", term.unparse(), reason)
+            }
+            Violation::Preservation { ref from, ref to, ref ty, ref found } => {
+                write!(f, "no preservation: {} : {} stepped to {}", from.unparse(), ty, to.unparse())?;
+                match *found {
+                    Ok(ref found) => write!(f, " : {}", found),
+                    Err(ref e) => write!(f, ", which is ill-typed: {}", e),
+                }
+            }
+        }
+    }
+}
+
+/// Steps `t` to a value, checking that every term on the way is a value or
+/// steps and keeps the type of `t`. Ill-typed terms and running out of
+/// `fuel` count as passing.
+pub fn check_safety(t: &Term, fuel: usize) -> Result<(), Violation> {
+    let mut tc = TypeCheckVisitor::new();
+    let ty = match tc.type_of(t) {
+        Ok(ty) => ty,
+        Err(_) => return Ok(()),
+    };
+
+    let mut t = t.clone();
+    for _ in 0..fuel {
+        match eval::step(&t) {
+            Step::Value => return Ok(()),
+            Step::Stuck(_, reason) => {
+                return Err(Violation::Progress {
+                               term: t,
+                               reason: reason,
+                           })
+            }
+            Step::Reduced(next, _) => {
+                let found = tc.type_of(&next);
+                if found.as_ref() != Ok(&ty) {
+                    return Err(Violation::Preservation {
+                                   from: t,
+                                   to: next,
+                                   ty: ty,
+                                   found: found,
+                               });
+                }
+                t = next;
+            }
+        }
+    }
+    Ok(())
+}
+
+const FUEL: usize = 10_000;
+
+/// Checks `runs` random terms of about `size` nodes. Returns the first
+/// violation, found for the smallest term that still shows one.
+pub fn quickcheck(seed: u64, runs: usize, size: usize) -> Result<(), (Term, Violation)> {
+    let mut g = Generator::new(seed);
+
+    for _ in 0..runs {
+        let ty = g.ty(2);
+        let t = g.term(&ty, size);
+
+        if check_safety(&t, FUEL).is_err() {
+            let t = minimize(&t, |t| check_safety(t, FUEL).is_err());
+            let v = check_safety(&t, FUEL).unwrap_err();
+            return Err((t, v));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        let mut g = Generator::new(7);
+        let mut tc = TypeCheckVisitor::new();
+
+        for size in 1..40 {
+            let ty = g.ty(2);
+            let t = g.term(&ty, size);
+            assert_eq!(tc.type_of(&t), Ok(ty.clone()), "{}", t.unparse());
+            assert!(t.get_vars().is_empty());
+        }
+
+        let t1 = Generator::new(3).term(&TyLit::Int.into(), 20);
+        let t2 = Generator::new(3).term(&TyLit::Int.into(), 20);
+        assert_eq!(t1, t2);
+    }
+
+    /// Whether `t` has an `if` whose condition is not a literal.
+    fn has_if(t: &Term) -> bool {
+        match *t {
+            Term::If(ref c, ..) if **c != Term::True && **c != Term::False => true,
+            Term::If(ref c, ref b1, ref b2) => has_if(c) || has_if(b1) || has_if(b2),
+            Term::App(ref f, ref args) => has_if(f) || args.iter().any(has_if),
+            Term::Abs(_, ref body) => has_if(body),
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn test_shrink() {
+        let t = ::corpus::get("(((/lam x: #Int, y: #Int. (/lam b: #B. (if b x y))) 3 4) #F)");
+        let ty = TypeCheckVisitor::new().type_of(&t).unwrap();
+
+        for s in shrink(&t) {
+            assert!(weight(&s) < weight(&t));
+            assert_eq!(TypeCheckVisitor::new().type_of(&s), Ok(ty.clone()));
+        }
+
+        let small = minimize(&t, has_if);
+        assert_eq!(small.unparse(), "((/lam : #B. (If 1 0 0)) #F)");
+        assert_eq!(minimize(&Term::Int(12), |t| *t != Term::Int(0)), Term::Int(1));
+    }
+
+    #[test]
+    fn test_safety() {
+        for seed in 0..4 {
+            if let Err((t, v)) = quickcheck(seed, 100, 30) {
+                panic!("seed {}: {} ({})", seed, v, t.unparse());
+            }
+        }
+
+        // Ill-typed terms are not counterexamples.
+        assert_eq!(check_safety(&::corpus::get("(if 1 #T #F)"), FUEL), Ok(()));
+    }
+}
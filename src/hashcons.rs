@@ -0,0 +1,318 @@
+//! Stable structural hashing and a hash-consed term store.
+//!
+//! The hashes only depend on the shape of a term, never on pointer values or
+//! on how `std` hashes things, so they can be stored and compared across runs
+//! and platforms. Named terms are hashed up to alpha-equivalence.
+
+use std::collections::HashMap;
+
+use core::{Term, Ty, TyLit};
+use toplevel::Term as TTerm;
+
+/// 64-bit FNV-1a.
+pub struct StableHasher {
+    state: u64,
+}
+
+impl StableHasher {
+    pub fn new() -> StableHasher {
+        StableHasher { state: 0xcbf29ce484222325 }
+    }
+
+    pub fn write_u8(&mut self, b: u8) {
+        self.state ^= b as u64;
+        self.state = self.state.wrapping_mul(0x100000001b3);
+    }
+
+    pub fn write_u64(&mut self, n: u64) {
+        for i in 0..8 {
+            self.write_u8((n >> (8 * i)) as u8);
+        }
+    }
+
+    pub fn write_str(&mut self, s: &str) {
+        self.write_u64(s.len() as u64);
+        for &b in s.as_bytes() {
+            self.write_u8(b);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.state
+    }
+}
+
+pub trait StructuralHash {
+    fn hash_into(&self, h: &mut StableHasher);
+
+    fn structural_hash(&self) -> u64 {
+        let mut h = StableHasher::new();
+        self.hash_into(&mut h);
+        h.finish()
+    }
+}
+
+impl StructuralHash for Ty {
+    fn hash_into(&self, h: &mut StableHasher) {
+        match *self {
+            Ty::Base(TyLit::Bool) => h.write_u8(0),
+            Ty::Base(TyLit::Int) => h.write_u8(1),
+            Ty::Arrow(ref args, ref res) => {
+                h.write_u8(2);
+                h.write_u64(args.len() as u64);
+                for arg in args {
+                    arg.hash_into(h);
+                }
+                res.hash_into(h);
+            }
+            Ty::Bottom => h.write_u8(3),
+        }
+    }
+}
+
+impl StructuralHash for Term {
+    fn hash_into(&self, h: &mut StableHasher) {
+        match *self {
+            Term::Var(k) => {
+                h.write_u8(0);
+                h.write_u64(k as u64);
+            }
+            Term::Abs(ref tys, ref body) => {
+                h.write_u8(1);
+                h.write_u64(tys.len() as u64);
+                for ty in tys {
+                    ty.hash_into(h);
+                }
+                body.hash_into(h);
+            }
+            Term::App(ref f, ref args) => {
+                h.write_u8(2);
+                f.hash_into(h);
+                h.write_u64(args.len() as u64);
+                for arg in args {
+                    arg.hash_into(h);
+                }
+            }
+            Term::True => h.write_u8(3),
+            Term::False => h.write_u8(4),
+            Term::Int(n) => {
+                h.write_u8(5);
+                h.write_u64(n as u64);
+            }
+            Term::Not => h.write_u8(6),
+            Term::If(ref c, ref b1, ref b2) => {
+                h.write_u8(7);
+                c.hash_into(h);
+                b1.hash_into(h);
+                b2.hash_into(h);
+            }
+            Term::Stuck => h.write_u8(8),
+            Term::Hole(ref x) => {
+                h.write_u8(10);
+                h.write_str(x);
+            }
+        }
+    }
+}
+
+/// Hashes bound variables by binder distance and free ones by name, so
+/// alpha-equivalent terms hash the same.
+fn hash_named<'a>(t: &'a TTerm, scope: &mut Vec<&'a str>, h: &mut StableHasher) {
+    match *t {
+        TTerm::Var(ref x) => {
+            match scope.iter().rev().position(|y| y == x) {
+                Some(i) => {
+                    h.write_u8(0);
+                    h.write_u64(i as u64);
+                }
+                None => {
+                    h.write_u8(9);
+                    h.write_str(x);
+                }
+            }
+        }
+        TTerm::Abs(ref args, ref body) => {
+            h.write_u8(1);
+            h.write_u64(args.len() as u64);
+            for &(ref x, ref ty) in args {
+                ty.hash_into(h);
+                scope.push(x);
+            }
+            hash_named(body, scope, h);
+            let len = scope.len() - args.len();
+            scope.truncate(len);
+        }
+        TTerm::App(ref f, ref args) => {
+            h.write_u8(2);
+            hash_named(f, scope, h);
+            h.write_u64(args.len() as u64);
+            for arg in args {
+                hash_named(arg, scope, h);
+            }
+        }
+        TTerm::True => h.write_u8(3),
+        TTerm::False => h.write_u8(4),
+        TTerm::Int(n) => {
+            h.write_u8(5);
+            h.write_u64(n as u64);
+        }
+        TTerm::Not => h.write_u8(6),
+        TTerm::If(ref c, ref b1, ref b2) => {
+            h.write_u8(7);
+            hash_named(c, scope, h);
+            hash_named(b1, scope, h);
+            hash_named(b2, scope, h);
+        }
+        TTerm::Bottom => h.write_u8(8),
+        TTerm::Hole(ref x) => {
+            h.write_u8(10);
+            h.write_str(x);
+        }
+        TTerm::Error => h.write_u8(11),
+    }
+}
+
+impl StructuralHash for TTerm {
+    fn hash_into(&self, h: &mut StableHasher) {
+        hash_named(self, &mut Vec::new(), h);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, PartialOrd, Ord)]
+pub struct TermId(usize);
+
+/// A term node whose children are shared through the store.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Node {
+    Var(u32),
+    Abs(Vec<Ty>, TermId),
+    App(TermId, Vec<TermId>),
+    True,
+    False,
+    Int(i64),
+    Not,
+    If(TermId, TermId, TermId),
+    Stuck,
+    Hole(String),
+}
+
+/// Stores every distinct subterm once; equal terms get equal ids.
+pub struct TermStore {
+    nodes: Vec<Node>,
+    ids: HashMap<Node, TermId>,
+}
+
+impl TermStore {
+    pub fn new() -> TermStore {
+        TermStore {
+            nodes: Vec::new(),
+            ids: HashMap::new(),
+        }
+    }
+
+    /// Number of distinct subterms stored.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn get(&self, id: TermId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    fn node(&mut self, node: Node) -> TermId {
+        if let Some(&id) = self.ids.get(&node) {
+            return id;
+        }
+
+        let id = TermId(self.nodes.len());
+        self.nodes.push(node.clone());
+        self.ids.insert(node, id);
+        id
+    }
+
+    pub fn intern(&mut self, t: &Term) -> TermId {
+        let node = match *t {
+            Term::Var(k) => Node::Var(k),
+            Term::Abs(ref tys, ref body) => Node::Abs(tys.clone(), self.intern(body)),
+            Term::App(ref f, ref args) => {
+                let f = self.intern(f);
+                Node::App(f, args.iter().map(|a| self.intern(a)).collect())
+            }
+            Term::True => Node::True,
+            Term::False => Node::False,
+            Term::Int(n) => Node::Int(n),
+            Term::Not => Node::Not,
+            Term::If(ref c, ref b1, ref b2) => {
+                Node::If(self.intern(c), self.intern(b1), self.intern(b2))
+            }
+            Term::Stuck => Node::Stuck,
+            Term::Hole(ref x) => Node::Hole(x.clone()),
+        };
+
+        self.node(node)
+    }
+
+    pub fn to_term(&self, id: TermId) -> Term {
+        match *self.get(id) {
+            Node::Var(k) => Term::Var(k),
+            Node::Abs(ref tys, body) => Term::abs(tys, self.to_term(body)),
+            Node::App(f, ref args) => {
+                Term::App(Box::new(self.to_term(f)),
+                          args.iter().map(|&a| self.to_term(a)).collect())
+            }
+            Node::True => Term::True,
+            Node::False => Term::False,
+            Node::Int(n) => Term::Int(n),
+            Node::Not => Term::Not,
+            Node::If(c, b1, b2) => Term::if_(self.to_term(c), self.to_term(b1), self.to_term(b2)),
+            Node::Stuck => Term::Stuck,
+            Node::Hole(ref x) => Term::Hole(x.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use corpus::{get, PROGRAMS};
+    use lispy;
+
+    #[test]
+    fn test_hash() {
+        let named = |s: &str| lispy::get_code(s.as_bytes()).unwrap();
+
+        assert_eq!(named("(/lam x: #B. (x y))").structural_hash(),
+                   named("(/lam z: #B. (z y))").structural_hash());
+        assert!(named("(/lam x: #B. (x y))").structural_hash() !=
+                named("(/lam x: #B. (x z))").structural_hash());
+
+        for (i, a) in PROGRAMS.iter().enumerate() {
+            for b in &PROGRAMS[i + 1..] {
+                assert!(get(a).structural_hash() != get(b).structural_hash(), "{} {}", a, b);
+            }
+        }
+
+        // Pinned so accidental changes to the hash function are noticed.
+        assert_eq!(Term::True.structural_hash(), 0xaf63be4c8601b992);
+    }
+
+    #[test]
+    fn test_store() {
+        let mut store = TermStore::new();
+
+        let t = get("((/lam x: #B, y: #B. (if x y (! y))) (! #T) (! #T))");
+        let id = store.intern(&t);
+        assert_eq!(store.to_term(id), t);
+
+        // x, y, (! y), the if, the lambda, !, #T, (! #T) and the application
+        assert_eq!(store.len(), 9);
+        assert_eq!(store.intern(&get("(! #T)")), store.intern(&get("(! #T)")));
+        assert_eq!(store.len(), 9);
+
+        for code in PROGRAMS {
+            let t = get(code);
+            let id = store.intern(&t);
+            assert_eq!(store.to_term(id), t);
+        }
+    }
+}
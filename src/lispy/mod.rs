@@ -2,10 +2,23 @@ pub mod tokens;
 pub mod tokenize;
 pub mod scan;
 
+use core::Ty;
 use errors::*;
+use span::{Diagnostic, Spans};
 use toplevel::Term;
 
 pub fn get_code(data: &[u8]) -> Result<Term> {
     let lex = tokenize::scan(data)?;
     scan::parse(lex)
 }
+
+/// Like `get_code`, also returning the source range of every node.
+pub fn get_code_spanned(src: &str) -> ::std::result::Result<(Term, Spans), Vec<Diagnostic>> {
+    let lex = tokenize::scan(src.as_bytes()).map_err(|d| vec![d])?;
+    scan::parse_spanned(lex)
+}
+
+pub fn get_type(data: &[u8]) -> Result<Ty> {
+    let lex = tokenize::scan(data)?;
+    scan::parse_type(lex)
+}
@@ -1,148 +1,278 @@
+use std::cmp;
+use std::collections::HashMap;
+
 use slog::{Drain, Logger};
 use slog_async;
 use slog_term;
 
-use core::{Ty, TyLit};
+use core::Ty;
 use errors::*;
 use lispy::tokens::Token as T;
 use lispy::tokenize::Lex;
+use span::{Diagnostic, Span, Spans};
 use toplevel::Term;
 
 
+/// A result of the parser, or the diagnostic explaining why it failed.
+type Parse<T> = ::std::result::Result<T, Diagnostic>;
+
+/// A recursive descent parser that recovers from errors at parenthesis
+/// boundaries. A list that fails to parse becomes a `Term::Error` and
+/// parsing continues after its `)`, so one pass finds every error that is
+/// not nested inside another.
 pub struct Parser<'a> {
     lex: Lex<'a>,
-    idx: isize,
+    /// Index of the next token.
+    pos: usize,
     logger: Logger,
+    /// Path of the node being parsed, numbered as in `eval::redex`.
+    path: Vec<usize>,
+    spans: Spans,
+    /// The index of the `)` matching each `(`, if it has one.
+    closers: Vec<Option<usize>>,
+    /// Whether each token is a `)` that matches no `(`.
+    stray: Vec<bool>,
+    /// Whether the input ends inside a `(`, so running out of tokens is
+    /// already explained.
+    unclosed: bool,
+    diagnostics: Vec<Diagnostic>,
+    /// Names that stand for a type in annotations.
+    ty_vars: HashMap<String, Ty>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(lex: Lex<'a>, logger: &Logger) -> Parser<'a> {
-        let p = Parser {
+        let mut p = Parser {
             logger: logger.new(o!("input" => lex.orig.to_owned(), "phase" => "Parsing")),
+            closers: vec![None; lex.tokens.len()],
+            stray: vec![false; lex.tokens.len()],
+            unclosed: false,
             lex: lex,
-            idx: -1,
+            pos: 0,
+            path: Vec::new(),
+            spans: Spans::new(),
+            diagnostics: Vec::new(),
+            ty_vars: HashMap::new(),
         };
 
         trace!(p.logger, "New parser"; "size" => p.lex.tokens.len());
+        p.balance();
 
         p
     }
 
-    fn expect(&mut self, token: T) -> Result<()> {
-        let t = self.next()?;
+    /// Matches up parentheses before parsing, reporting each one left over.
+    fn balance(&mut self) {
+        let mut open = Vec::new();
+
+        for (i, t) in self.lex.tokens.iter().enumerate() {
+            match *t {
+                T::LParen => open.push(i),
+                T::RParen => {
+                    match open.pop() {
+                        Some(j) => self.closers[j] = Some(i),
+                        None => {
+                            self.stray[i] = true;
+                            let d = Diagnostic::new("This ) does not close any (",
+                                                    Some(self.lex.spans[i]));
+                            self.diagnostics.push(d);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
 
-        if t == token {
-            Ok(())
-        } else {
-            Err(parse_error("Expected literal failed to match", Some(t)))
+        self.unclosed = !open.is_empty();
+        for &i in &open {
+            let d = Diagnostic::new("This ( is never closed", Some(self.lex.spans[i]));
+            self.diagnostics.push(d);
         }
     }
 
-    fn peek_expect(&mut self, token: T) -> Result<()> {
-        let ret = self.expect(token);
-        self.idx -= 1;
-        ret
+    /// The end of the list opened by the `(` at `open`.
+    fn closer(&self, open: usize) -> usize {
+        self.closers[open].unwrap_or(self.lex.tokens.len())
     }
 
-    fn parse_lambda(&mut self) -> Result<Term> {
-        trace!(self.logger, "parsing lambda");
-
-        let mut args = vec![];
+    fn span_at(&self, i: usize) -> Span {
+        let len = self.lex.orig.len();
+        self.lex.spans.get(i).cloned().unwrap_or_else(|| Span::new(self.lex.orig, len, len))
+    }
 
-        loop {
-            let var = self.parse_var()?;
-            self.expect(T::Colon)?;
+    /// A diagnostic for the token at `pos` not being `what`.
+    fn expected(&self, what: &str) -> Diagnostic {
+        let found = match self.lex.tokens.get(self.pos) {
+            Some(t) => format!("'{}'", t.unparse()),
+            None => "end of input".into(),
+        };
 
-            let ty = self.parse_ty()?;
+        Diagnostic::new(format!("Expected {}, found {}", what, found), Some(self.span_at(self.pos)))
+    }
 
-            if let Term::Var(x) = var {
-                args.push((x, ty));
-            } else {
-                return Err(parse_error("Var was not a var", None));
-            }
+    fn report(&mut self, d: Diagnostic) {
+        let at_end = d.span.map_or(false, |s| s.lo == self.lex.orig.len());
+        if !(at_end && self.unclosed) {
+            self.diagnostics.push(d);
+        }
+    }
 
-            let test = self.expect(T::Comma).is_ok();
-            if !test {
-                self.idx -= 1;
-                break;
-            }
+    /// The next token, if it comes before `end`.
+    fn peek_in(&self, end: usize) -> Option<&T> {
+        if self.pos < end {
+            self.lex.tokens.get(self.pos)
+        } else {
+            None
         }
+    }
 
-        self.expect(T::Dot)?;
+    fn expect(&mut self, token: T, end: usize) -> Parse<()> {
+        if self.peek_in(end) == Some(&token) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.expected(&format!("'{}'", token.unparse())))
+        }
+    }
 
-        let body = self.parse_term()?;
-        self.expect(T::RParen)?;
+    fn parse_lambda(&mut self, end: usize) -> Parse<Term> {
+        trace!(self.logger, "parsing lambda");
 
+        let mut args = vec![];
 
-        Ok(Term::Abs(args, Box::new(body)))
-    }
+        loop {
+            let x = match self.peek_in(end) {
+                Some(&T::Id(ref x)) => x.clone(),
+                _ => return Err(self.expected("a parameter name")),
+            };
+            self.pos += 1;
+            self.expect(T::Colon, end)?;
+
+            let ty = self.parse_ty(end)?;
+            args.push((x, ty));
+
+            match self.peek_in(end) {
+                Some(&T::Comma) => self.pos += 1,
+                Some(&T::Dot) => break,
+                _ => return Err(self.expected("',' or '.'")),
+            }
+        }
 
-    fn parse_var(&mut self) -> Result<Term> {
-        let t = self.next()?;
-        trace!(self.logger, "parsing var"; "next token" => &t);
+        self.expect(T::Dot, end)?;
+        let body = self.parse_child(0, end)?;
 
-        if let T::Id(name) = t {
-            Ok(Term::Var(name))
-        } else {
-            Err(parse_error("Error parsing var: unexpected token encountered", Some(t)))
-        }
+        Ok(Term::Abs(args, Box::new(body)))
     }
 
-    fn parse_app(&mut self) -> Result<Term> {
+    fn parse_app(&mut self, end: usize) -> Parse<Term> {
         trace!(self.logger, "parsing application");
-        self.idx -= 1;
-        let f = self.parse_term()?;
+        let f = self.parse_child(0, end)?;
 
         let mut args = vec![];
-
-
-        loop {
+        while self.pos < end {
             trace!(self.logger, "parsing app arg");
-            let x = self.parse_term()?;
-            let test = self.expect(T::RParen).is_ok();
-
+            let x = self.parse_child(args.len() + 1, end)?;
             args.push(x);
-            if test {
-                break;
-            } else {
-                self.idx -= 1;
-                trace!(self.logger, "another one");
-            }
+        }
+
+        if args.is_empty() {
+            return Err(self.expected("an argument"));
         }
 
         trace!(self.logger, "done parsing application");
         Ok(Term::app(f, &args))
     }
 
-    fn parse_if(&mut self) -> Result<Term> {
+    fn parse_if(&mut self, end: usize) -> Parse<Term> {
         trace!(self.logger, "parsing if");
-        let cond = self.parse_term()?;
-        let then = self.parse_term()?;
-        let else_ = self.parse_term()?;
-        self.expect(T::RParen)?;
 
+        let mut parts = Vec::new();
+        for (i, what) in ["a condition", "a then-branch", "an else-branch"].iter().enumerate() {
+            if self.pos >= end {
+                return Err(self.expected(what));
+            }
+            parts.push(self.parse_child(i, end)?);
+        }
+
+        let else_ = parts.pop().unwrap();
+        let then = parts.pop().unwrap();
+        let cond = parts.pop().unwrap();
         Ok(Term::if_(cond, then, else_))
     }
 
-    fn parse_ty(&mut self) -> Result<Ty> {
+    fn parse_ty(&mut self, end: usize) -> Parse<Ty> {
         trace!(self.logger, "parsing type");
 
-        match self.next()? {
-            T::TypeLit(ref lit) => Ok((*lit).into()),
-            T::LParen => {
-                let ty1 = self.parse_ty()?;
-                self.expect(T::Arrow)?;
-                let ty2 = self.parse_ty()?;
-                self.expect(T::RParen)?;
+        let ty = match self.peek_in(end) {
+            Some(&T::TypeLit(lit)) => Some(lit.into()),
+            Some(&T::Id(ref x)) if self.ty_vars.contains_key(x) => Some(self.ty_vars[x].clone()),
+            Some(&T::LParen) => None,
+            _ => return Err(self.expected("a type")),
+        };
+        self.pos += 1;
+        if let Some(ty) = ty {
+            return Ok(ty);
+        }
 
-                Ok(Ty::arrow(&[ty1], ty2))
+        let close = self.closer(self.pos - 1);
+        let mut params = vec![self.parse_ty(close)?];
+        loop {
+            match self.peek_in(close) {
+                Some(&T::Comma) => {
+                    self.pos += 1;
+                    params.push(self.parse_ty(close)?);
+                }
+                Some(&T::Arrow) => break,
+                _ => return Err(self.expected("',' or '->'")),
             }
-            t => Err(parse_error("Error parsing type: unexpected token encountered", Some(t))),
         }
+
+        self.expect(T::Arrow, close)?;
+        let res = self.parse_ty(close)?;
+        if self.pos < close {
+            return Err(self.expected("')'"));
+        }
+        self.pos = close + 1;
+
+        Ok(Ty::arrow(&params, res))
     }
 
-    fn parse_term(&mut self) -> Result<Term> {
-        let tok = self.next()?;
+    /// Parses a child, which on failure becomes a `Term::Error` that only
+    /// fails the parent if the parent's list has run out.
+    fn parse_child(&mut self, i: usize, end: usize) -> Parse<Term> {
+        if self.pos >= end {
+            return Err(self.expected("a term"));
+        }
+
+        self.path.push(i);
+        let ret = self.parse_term(end);
+        self.path.pop();
+        Ok(ret)
+    }
+
+    /// Parses a term ending before `end`, reporting any error it contains.
+    fn parse_term(&mut self, end: usize) -> Term {
+        let start = self.pos;
+        let term = match self.parse_term_inner(end) {
+            Ok(term) => term,
+            Err(d) => {
+                self.report(d);
+                Term::Error
+            }
+        };
+
+        let last = cmp::max(self.pos, start + 1) - 1;
+        let span = self.span_at(start).to(self.span_at(last));
+        self.spans.insert(self.path.clone(), span);
+        term
+    }
+
+    fn parse_term_inner(&mut self, end: usize) -> Parse<Term> {
+        let tok = match self.peek_in(end) {
+            Some(t) => t.clone(),
+            None => return Err(self.expected("a term")),
+        };
+        self.pos += 1;
 
         trace!(self.logger, "parsing term"; "next token" => &tok);
 
@@ -150,67 +280,156 @@ impl<'a> Parser<'a> {
             T::True => Ok(Term::True),
             T::False => Ok(Term::False),
             T::Not => Ok(Term::Not),
-            T::LParen => {
-                match self.next()? {
-                    T::Lambda => self.parse_lambda(),
-                    T::If => self.parse_if(),
-                    _ => self.parse_app(),
-                }
-            }
+            T::LParen => self.parse_list(),
             T::Id(ref x) => Ok(Term::Var(x.clone())),
             T::Int(n) => Ok(Term::Int(n)),
-            t => Err(parse_error("Error parsing term: unexpected token encountered", Some(t))),
+            T::Hole(ref x) => Ok(Term::Hole(x.clone())),
+            _ => {
+                self.pos -= 1;
+                let d = self.expected("a term");
+                self.pos += 1;
+                Err(d)
+            }
         }
     }
 
-    fn next(&mut self) -> Result<T> {
-        self.idx += 1;
-        let t = self.lex
-            .tokens
-            .get(self.idx as usize)
-            .ok_or(parse_error("Reached end of input", None))?;
+    /// Parses the list whose `(` was just read. Whatever happens, parsing
+    /// continues after its `)`.
+    fn parse_list(&mut self) -> Parse<Term> {
+        let open = self.pos - 1;
+        let close = self.closer(open);
 
-        Ok(t.clone())
+        let ret = match self.peek_in(close) {
+            Some(&T::Lambda) => {
+                self.pos += 1;
+                self.parse_lambda(close)
+            }
+            Some(&T::If) => {
+                self.pos += 1;
+                self.parse_if(close)
+            }
+            Some(_) => self.parse_app(close),
+            None => Err(Diagnostic::new("Empty ()", Some(self.span_at(open).to(self.span_at(close))))),
+        };
+        let ret = match ret {
+            Ok(_) if self.pos < close => Err(self.expected("')'")),
+            ret => ret,
+        };
+
+        self.pos = cmp::min(close + 1, self.lex.tokens.len());
+        ret
     }
 
-    fn prev(&mut self) -> Result<T> {
-        self.idx -= 1;
-        let t = self.lex
-            .tokens
-            .get(self.idx as usize)
-            .ok_or(parse_error("Backtrack at start of input", None))?;
+    /// Parses the whole input as one term, reporting anything after it.
+    fn parse_program(&mut self) -> Term {
+        let len = self.lex.tokens.len();
+        while self.pos < len && self.stray[self.pos] {
+            self.pos += 1;
+        }
 
-        Ok(t.clone())
+        let term = self.parse_term(len);
+
+        while self.pos < len {
+            if !self.stray[self.pos] {
+                let span = self.span_at(self.pos).to(self.span_at(len - 1));
+                self.report(Diagnostic::new("Unexpected input after the end of the term", Some(span)));
+                break;
+            }
+            self.pos += 1;
+        }
+
+        self.diagnostics.sort_by_key(|d| d.span.map(|s| s.lo));
+        term
     }
 }
 
-pub fn parse(tokens: Lex) -> Result<Term> {
+/// A parse that kept going past errors.
+pub struct Parsed {
+    /// The term, with a `Term::Error` for each part that failed to parse.
+    pub term: Term,
+    pub spans: Spans,
+    /// Every error found, in source order.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+fn logger() -> Logger {
     let decorator = slog_term::TermDecorator::new().build();
     let drain = slog_term::FullFormat::new(decorator).build().fuse();
     let drain = slog_async::Async::new(drain).build().fuse();
 
-    let _log = Logger::root(drain, o!("context" => "main"));
+    Logger::root(drain, o!("context" => "main"))
+}
+
+fn join(ds: &[Diagnostic]) -> Error {
+    let messages: Vec<String> = ds.iter().map(|d| d.to_string()).collect();
+    messages.join("\n").into()
+}
+
+pub fn parse(tokens: Lex) -> Result<Term> {
+    parse_spanned(tokens).map(|(t, _)| t).map_err(|ds| join(&ds))
+}
+
+/// Like `parse`, reading each name in `vars` as its type where an annotation
+/// is expected. Elsewhere the names are ordinary identifiers.
+pub fn parse_with_type_vars(tokens: Lex, vars: &HashMap<String, Ty>) -> Result<Term> {
+    let mut p = Parser::new(tokens, &logger());
+    p.ty_vars = vars.clone();
+    let term = p.parse_program();
+
+    if p.diagnostics.is_empty() {
+        Ok(term)
+    } else {
+        Err(join(&p.diagnostics))
+    }
+}
 
-    let mut p = Parser::new(tokens, &_log);
+/// Parses a type such as `((#B -> #B), #Int -> #B)`, rejecting trailing input.
+pub fn parse_type(tokens: Lex) -> Result<Ty> {
+    let len = tokens.tokens.len();
+    let mut p = Parser::new(tokens, &logger());
+    if !p.diagnostics.is_empty() {
+        return Err(p.diagnostics.remove(0).into());
+    }
 
-    p.parse_term()
+    let ty = p.parse_ty(len)?;
+    if p.pos < len {
+        return Err(p.expected("the end of the type").into());
+    }
+    Ok(ty)
+}
+
+/// Parses a term and records the source range of every node, failing with
+/// every error found.
+pub fn parse_spanned(tokens: Lex) -> ::std::result::Result<(Term, Spans), Vec<Diagnostic>> {
+    let parsed = parse_recovering(tokens);
+
+    if parsed.diagnostics.is_empty() {
+        Ok((parsed.term, parsed.spans))
+    } else {
+        Err(parsed.diagnostics)
+    }
+}
+
+/// Parses as much of a term as possible, collecting every error on the way.
+pub fn parse_recovering(tokens: Lex) -> Parsed {
+    let mut p = Parser::new(tokens, &logger());
+    let term = p.parse_program();
+
+    Parsed {
+        term: term,
+        spans: p.spans,
+        diagnostics: p.diagnostics,
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use core::TyLit;
     use lispy::tokenize::scan;
 
     fn get_parser<'a>(bytes: &'a [u8]) -> Result<Term> {
-        let lex = scan(bytes).unwrap();
-
-        let decorator = slog_term::TermDecorator::new().build();
-        let drain = slog_term::FullFormat::new(decorator).build().fuse();
-        let drain = slog_async::Async::new(drain).build().fuse();
-
-        let _log = Logger::root(drain, o!("context" => "test"));
-
-        Parser::new(lex, &_log).parse_term()
+        parse(scan(bytes).unwrap())
     }
 
     #[test]
@@ -261,4 +480,84 @@ mod test {
                       &[Term::True, Term::Int(2000)]);
         assert_eq!(p.unwrap(), test);
     }
+
+    #[test]
+    fn test_parse_type() {
+        let ty = |s: &str| parse_type(scan(s.as_bytes()).unwrap());
+        let b: Ty = TyLit::Bool.into();
+
+        assert_eq!(ty("#Int").unwrap(), TyLit::Int.into());
+        assert_eq!(ty("((#B -> #B), #B -> #B)").unwrap(),
+                   Ty::arrow(&[Ty::arrow(&[b.clone()], b.clone()), b.clone()], b.clone()));
+        assert_eq!(ty("(#B -> (#B -> #B))").unwrap().unparse(), "(#B -> (#B -> #B))");
+        assert!(ty("#B #B").is_err());
+        assert!(ty("(#B ->").is_err());
+    }
+
+    #[test]
+    fn test_spans() {
+        let src = "((/lam x: #B. (if x 1 -2))\n #T)";
+        let (_, spans) = parse_spanned(scan(src.as_bytes()).unwrap()).unwrap();
+        let text = |path: &[usize]| {
+            let span = spans.get(path).unwrap();
+            &src[span.lo..span.hi]
+        };
+
+        assert_eq!(spans.len(), 7);
+        assert_eq!(text(&[]), src);
+        assert_eq!(text(&[0]), "(/lam x: #B. (if x 1 -2))");
+        assert_eq!(text(&[0, 0]), "(if x 1 -2)");
+        assert_eq!(text(&[0, 0, 2]), "-2");
+        assert_eq!(text(&[1]), "#T");
+        assert_eq!(spans.get(&[1]).unwrap().line, 2);
+        assert_eq!(spans.get(&[1]).unwrap().col, 2);
+
+        let ds = parse_spanned(scan(b"(if #T : #F)").unwrap()).unwrap_err();
+        assert_eq!(ds[0].span.unwrap().lo, 7);
+        let ds = parse_spanned(scan(b"((/lam x: #B. x) #T").unwrap()).unwrap_err();
+        assert_eq!(ds.len(), 1);
+        assert_eq!(ds[0].span.unwrap().lo, 0);
+    }
+
+    #[test]
+    fn test_recover() {
+        let parse = |s: &str| {
+            let p = parse_recovering(scan(s.as_bytes()).unwrap());
+            let ds: Vec<String> = p.diagnostics.iter().map(|d| d.to_string()).collect();
+            (p.term.pretty(), ds)
+        };
+
+        let (t, ds) = parse("((/lam x #B. x) (if #T #F) (! :))");
+        assert_eq!(t, "(<error> <error> (! <error>))");
+        assert_eq!(ds,
+                   vec!["Expected ':', found '#B' at 1:10",
+                        "Expected an else-branch, found ')' at 1:26",
+                        "Expected a term, found ':' at 1:31"]);
+
+        let (t, ds) = parse("(/lam x: #B. (if x #T #F)");
+        assert_eq!(t, "(/lam x: #B. (if x #T #F))");
+        assert_eq!(ds, vec!["This ( is never closed at 1:1"]);
+
+        let (t, ds) = parse("((/lam x: #B. x)) #T)");
+        assert_eq!(t, "<error>");
+        assert_eq!(ds,
+                   vec!["Expected an argument, found ')' at 1:17",
+                        "Unexpected input after the end of the term at 1:19",
+                        "This ) does not close any ( at 1:21"]);
+
+        let (t, ds) = parse(") (f ()) x");
+        assert_eq!(t, "(f <error>)");
+        assert_eq!(ds,
+                   vec!["This ) does not close any ( at 1:1",
+                        "Empty () at 1:6",
+                        "Unexpected input after the end of the term at 1:10"]);
+
+        let (t, ds) = parse("(/lam f: (#B #B). (f #T))");
+        assert_eq!(t, "<error>");
+        assert_eq!(ds, vec!["Expected ',' or '->', found '#B' at 1:14"]);
+
+        assert_eq!(parse(""), ("<error>".into(), vec!["Expected a term, found end of input at 1:1".into()]));
+        assert_eq!(parse("((f"), ("<error>".into(), vec!["This ( is never closed at 1:1".into(),
+                                                              "This ( is never closed at 1:2".into()]));
+    }
 }
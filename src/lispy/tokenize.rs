@@ -1,10 +1,10 @@
 use std::str::from_utf8;
 
-use nom::{Slice, alpha, alphanumeric, digit};
+use nom::{IResult, Slice, alpha, alphanumeric, digit};
 
 use core::TyLit;
-use errors::*;
 use lispy::tokens::Token;
+use span::{Diagnostic, Span};
 
 #[allow(non_camel_case_types)]
 type bytes<'a> = &'a [u8];
@@ -12,6 +12,8 @@ type bytes<'a> = &'a [u8];
 pub struct Lex<'a> {
     pub orig: &'a str,
     pub tokens: Vec<Token>,
+    /// The source range of each token.
+    pub spans: Vec<Span>,
 }
 
 named!(false_<&[u8], Token>, map!(tag!("#F"), |_| Token::False ));
@@ -65,19 +67,49 @@ named!(rparen( bytes ) -> Token, map!(tag!(")"), |_| Token::RParen));
 
 named!(bottom( bytes ) -> Token, map!(tag!("_|_"), |_| Token::Bottom));
 
+named!(hole( bytes ) -> Token, map!(preceded!(tag!("?"), identifier), |s: &str| Token::Hole(s.into())));
+
 named!(token (bytes) -> Token, alt!(false_ | true_ | not | if_token | variable | comma
-| lambda | colon | bool_ | arrow | dot | lparen | rparen | bottom | int | int_ty));
+| lambda | colon | bool_ | arrow | dot | lparen | rparen | bottom | hole | int | int_ty));
 
 named!(tokenize(bytes) -> Vec<Token>, ws!(many0!(token)));
 
-pub fn scan<'a>(data: &'a [u8]) -> Result<Lex<'a>> {
-    use std::str::from_utf8;
-
-    let toks = tokenize(data).to_full_result()?;
-    let s = from_utf8(data)?;
+pub fn scan<'a>(data: &'a [u8]) -> Result<Lex<'a>, Diagnostic> {
+    let s = from_utf8(data).map_err(|e| Diagnostic::new(format!("Invalid UTF-8: {}", e), None))?;
+    let mut tokens = Vec::new();
+    let mut spans: Vec<Span> = Vec::new();
+    let mut pos = 0;
+    // Line and column are counted on from the previous token.
+    let span = |spans: &[Span], lo, hi| match spans.last() {
+        Some(&prev) => Span::after(prev, s, lo, hi),
+        None => Span::new(s, lo, hi),
+    };
+
+    loop {
+        pos += data[pos..].iter().take_while(|b| (**b as char).is_whitespace()).count();
+        if pos == data.len() {
+            break;
+        }
+
+        match token(&data[pos..]) {
+            IResult::Done(rest, tok) => {
+                let end = data.len() - rest.len();
+                tokens.push(tok);
+                spans.push(span(&spans, pos, end));
+                pos = end;
+            }
+            _ => {
+                let end = pos + s[pos..].chars().next().map_or(1, |c| c.len_utf8());
+                let c = &s[pos..end];
+                return Err(Diagnostic::new(format!("Unexpected character {:?}", c),
+                                           Some(span(&spans, pos, end))));
+            }
+        }
+    }
 
     Ok(Lex {
-           tokens: toks,
+           tokens: tokens,
+           spans: spans,
            orig: s,
        })
 }
@@ -88,7 +120,6 @@ mod test {
 
     use lispy::tokens::Token as T;
 
-    use nom::IResult;
     use nom::Needed::Size;
 
 
@@ -17,6 +17,7 @@ pub enum Token {
     LParen,
     RParen,
     Bottom,
+    Hole(String),
     TypeLit(TyLit),
 }
 
@@ -40,6 +41,7 @@ impl Token {
             LParen => "(".into(),
             RParen => ")".into(),
             Bottom => "_|_".into(),
+            Hole(ref s) => format!("?{}", s),
         }
     }
 }
@@ -17,13 +17,37 @@ mod toplevel;
 mod visitor;
 mod core;
 mod eval;
+mod cek;
+mod bytecode;
+mod closure;
+mod cps;
+mod anf;
+mod untyped;
+mod opt;
+mod hashcons;
+mod nameless;
+mod debugger;
+mod trace;
+mod span;
+mod derivation;
+mod annotate;
+mod synth;
+mod gen;
+mod freethm;
+mod proof;
+#[cfg(feature = "arena")]
+mod arena;
+mod backend;
 mod errors;
 mod lispy;
 mod typecheck;
 mod debrujin;
+#[cfg(test)]
+mod corpus;
 
+use std::fs::File;
 use std::io;
-use std::io::stdin;
+use std::io::{stdin, Write};
 
 use slog::{Drain, Logger};
 
@@ -37,20 +61,64 @@ fn root_logger() -> Logger {
     _log
 }
 
-fn inner(input: &str, logger: &Logger) -> errors::Result<(core::Term, core::Term, core::Ty)> {
-    let code = lispy::get_code(input.as_bytes())?;
-    {
-        let fv = visitor::FVVisitor::new(&code);
-        let _ = fv.check()?;
+const BENCH_RUNS: u32 = 1000;
+
+const SYNTH_DEPTH: usize = 3;
+const SYNTH_LIMIT: usize = 10;
+
+const FREE_RUNS: usize = 100;
+const FREE_SEED: u64 = 0x5eed;
+
+/// Parses, renames and typechecks a program that is about to be run or
+/// compiled, so it must not have holes.
+fn front(input: &str) -> errors::Result<(core::Term, core::Ty)> {
+    let (ast, ty, holes) = front_holes(input)?;
+    match ty {
+        Some(ty) if holes.is_empty() => Ok((ast, ty)),
+        _ => bail!("Cannot run or compile a program with unfilled holes\n{}", holes.join("\n")),
     }
+}
+
+/// Like `front`, allowing holes and describing each of them. The type is
+/// `None` when it depends on a hole whose type is not determined.
+fn front_holes(input: &str) -> errors::Result<(core::Term, Option<core::Ty>, Vec<String>)> {
+    let (code, spans) = parse(input)?;
+    check(input, &code, &spans)
+}
+
+/// Parses `input`, rendering every syntax error.
+fn parse(input: &str) -> errors::Result<(toplevel::Term, span::Spans)> {
+    lispy::get_code_spanned(input).map_err(|ds| {
+        let rendered: Vec<String> = ds.iter().map(|d| d.render(input)).collect();
+        rendered.join("\n").into()
+    })
+}
+
+/// Renames and typechecks `code`, parsed from `input` with `spans`.
+fn check(input: &str,
+         code: &toplevel::Term,
+         spans: &span::Spans)
+         -> errors::Result<(core::Term, Option<core::Ty>, Vec<String>)> {
+    let render = |d: span::Diagnostic| -> errors::Error { d.render(input).into() };
+
+    span::check_closed(code, spans).map_err(&render)?;
     let ast = {
         let mut rv = debrujin::RenameVisitor::new();
-        rv.rename_term(&code)?
-    };
-    let ty = {
-        let mut typechecker = typecheck::TypeCheckVisitor::new();
-        typechecker.type_of(&ast)?
+        rv.rename_term(code)?
     };
+    let mut typechecker = typecheck::TypeCheckVisitor::new();
+    let ty = typechecker.type_of_located(&ast)
+        .map_err(|(e, path)| render(span::Diagnostic::new(e.to_string(), spans.get(&path))))?;
+    let holes = typechecker.holes()
+        .iter()
+        .map(|h| h.report(&code.binders_at(&h.path).unwrap_or_default()))
+        .collect();
+
+    Ok((ast, ty, holes))
+}
+
+fn inner(input: &str, logger: &Logger) -> errors::Result<(core::Term, core::Term, core::Ty)> {
+    let (ast, ty) = front(input)?;
 
     let v = {
         let mut evaluator = eval::Evaluator::new(logger);
@@ -60,14 +128,255 @@ fn inner(input: &str, logger: &Logger) -> errors::Result<(core::Term, core::Term
     Ok((ast, v, ty))
 }
 
+fn session(debugger: &mut Option<debugger::Debugger>) -> errors::Result<&mut debugger::Debugger> {
+    debugger.as_mut().ok_or("No program is being debugged, start one with :step <program>".into())
+}
+
+fn proving(proof: &mut Option<proof::Proof>) -> errors::Result<&mut proof::Proof> {
+    proof.as_mut().ok_or("No proof in progress, start one with :prove <type>".into())
+}
+
+fn report(d: &debugger::Debugger, event: debugger::Event) {
+    match event {
+        debugger::Event::Stepped(rule) => println!("{}", rule),
+        debugger::Event::Breakpoint(i) => println!("Breakpoint {}: {}", i, d.breakpoints()[i - 1]),
+        debugger::Event::Value => {}
+        debugger::Event::Stuck(reason) => println!("Stuck: {}", reason),
+    }
+    println!("{}", d.where_());
+}
+
+fn command(input: &str,
+           debugger: &mut Option<debugger::Debugger>,
+           breakpoints: &mut Vec<String>,
+           proof: &mut Option<proof::Proof>,
+           logger: &Logger)
+           -> errors::Result<()> {
+    let input = input.trim();
+    let (cmd, rest) = match input.find(' ') {
+        Some(i) => (&input[..i], &input[i..]),
+        None => (input, ""),
+    };
+
+    match cmd {
+        ":disasm" => {
+            let (ast, _) = front(rest)?;
+            print!("{}", bytecode::compile(&ast)?.disassemble());
+        }
+        ":anf" => {
+            let (ast, _) = front(rest)?;
+            println!("{}", anf::convert(&ast)?.0.pretty());
+        }
+        ":opt" => {
+            let (ast, _) = front(rest)?;
+            let mut optimizer = opt::Optimizer::new(opt::Config::default());
+            println!("{}", optimizer.optimize(&ast).unparse());
+            println!("{}", optimizer.stats());
+        }
+        ":c" => {
+            let (ast, _) = front(rest)?;
+            print!("{}", backend::c::emit(&ast)?);
+        }
+        ":wat" => {
+            let (ast, _) = front(rest)?;
+            print!("{}", backend::wasm::emit(&ast)?);
+        }
+        ":bench" => {
+            let (ast, _) = front(rest)?;
+            println!("{}", bytecode::bench(&ast, BENCH_RUNS, logger)?);
+        }
+        #[cfg(feature = "arena")]
+        ":bench-arena" => {
+            let depth = match rest.trim().parse() {
+                Ok(depth) => depth,
+                Err(_) => bail!("Usage: :bench-arena <depth>"),
+            };
+            println!("{}", arena::bench(&arena::generate(depth), 10, logger)?);
+        }
+        ":step" if !rest.trim().is_empty() => {
+            let (ast, _) = front(rest)?;
+            match *debugger {
+                Some(ref mut d) => d.restart(&ast),
+                None => {
+                    let mut d = debugger::Debugger::new(&ast);
+                    for pattern in breakpoints.iter() {
+                        d.add_breakpoint(pattern);
+                    }
+                    *debugger = Some(d);
+                }
+            }
+            let d = session(debugger)?;
+            println!("{}", d.where_());
+        }
+        ":step" => {
+            let d = session(debugger)?;
+            let event = d.step();
+            report(d, event);
+        }
+        ":next" => {
+            let d = session(debugger)?;
+            let event = d.next();
+            report(d, event);
+        }
+        ":continue" => {
+            let d = session(debugger)?;
+            let event = d.continue_();
+            report(d, event);
+        }
+        ":break" => {
+            let pattern = rest.trim();
+            if pattern.is_empty() {
+                bail!("Usage: :break <rule or part of a redex>");
+            }
+            // Kept outside the session so breakpoints can be set before :step
+            breakpoints.push(pattern.into());
+            if let Some(ref mut d) = *debugger {
+                d.add_breakpoint(pattern);
+            }
+            println!("Breakpoint {}: {}", breakpoints.len(), pattern);
+        }
+        ":where" => {
+            println!("{}", session(debugger)?.where_());
+        }
+        ":parse" => {
+            let lex = lispy::tokenize::scan(rest.as_bytes()).map_err(|d| d.render(rest))?;
+            let parsed = lispy::scan::parse_recovering(lex);
+            for d in &parsed.diagnostics {
+                println!("{}", d.render(rest));
+            }
+            println!("{}", parsed.term.pretty());
+        }
+        ":types" => {
+            let (code, spans) = parse(rest)?;
+            check(rest, &code, &spans)?;
+            println!("{}", annotate::annotate(&code)?);
+        }
+        ":holes" => {
+            let (_, _, holes) = front_holes(rest)?;
+            if holes.is_empty() {
+                println!("No holes");
+            }
+            for h in holes {
+                println!("{}", h);
+            }
+        }
+        ":synth" => {
+            let ty = lispy::get_type(rest.as_bytes())?;
+            let found = synth::inhabitants(&ty, SYNTH_DEPTH, SYNTH_LIMIT);
+            if found.is_empty() {
+                println!("No inhabitant of {} up to depth {}", ty, SYNTH_DEPTH);
+            }
+            for t in found {
+                println!("{}", debrujin::name_term(&t)?.pretty());
+            }
+        }
+        ":free" => {
+            let scheme = freethm::Scheme::parse(rest)?;
+            println!("{}", freethm::theorem(&scheme));
+        }
+        ":free-check" => {
+            let (scheme, template) = match rest.find(';') {
+                Some(i) => (&rest[..i], rest[i + 1..].trim()),
+                None => bail!("Usage: :free-check <scheme> ; <program>"),
+            };
+            let scheme = freethm::Scheme::parse(scheme)?;
+            match freethm::check(&scheme, template, FREE_RUNS, FREE_SEED, logger)? {
+                Some(cex) => println!("Counterexample: {}", cex),
+                None => println!("Passed {} instance(s)", FREE_RUNS),
+            }
+        }
+        ":prop" => {
+            println!("{}", freethm::Scheme::parse(rest)?.to_prop());
+        }
+        ":prove" => {
+            let ty = lispy::get_type(rest.as_bytes())?;
+            *proof = Some(proof::Proof::new(&ty));
+            println!("{}", proving(proof)?);
+        }
+        ":intro" => {
+            let names: Vec<&str> = rest.split_whitespace().collect();
+            let p = proving(proof)?;
+            p.intro(&names)?;
+            println!("{}", p);
+        }
+        ":exact" => {
+            let t = lispy::get_code(rest.as_bytes())?;
+            let p = proving(proof)?;
+            p.exact(&t)?;
+            println!("{}", p);
+        }
+        ":apply" => {
+            let t = lispy::get_code(rest.as_bytes())?;
+            let p = proving(proof)?;
+            p.apply(&t)?;
+            println!("{}", p);
+        }
+        ":cases" => {
+            let t = lispy::get_code(rest.as_bytes())?;
+            let p = proving(proof)?;
+            p.cases(&t)?;
+            println!("{}", p);
+        }
+        ":auto" => {
+            let p = proving(proof)?;
+            p.auto(SYNTH_DEPTH)?;
+            println!("{}", p);
+        }
+        ":undo" => {
+            let p = proving(proof)?;
+            p.undo()?;
+            println!("{}", p);
+        }
+        ":derive" => {
+            let (ast, _, _) = front_holes(rest)?;
+            println!("{}", typecheck::TypeCheckVisitor::new().derive(&ast)?.to_ascii());
+        }
+        ":derive-latex" => {
+            let (ast, _, _) = front_holes(rest)?;
+            print!("{}", typecheck::TypeCheckVisitor::new().derive(&ast)?.to_latex()?);
+        }
+        ":trace" => {
+            let usage = "Usage: :trace <json|html> <file> <program>";
+            let mut parts = rest.trim().splitn(3, ' ');
+            let (format, file, program) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(format), Some(file), Some(program)) => (format, file, program),
+                _ => bail!(usage),
+            };
+
+            let (ast, _) = front(program)?;
+            let trace = trace::record(&ast, None);
+            let out = match format {
+                "json" => trace.to_json_lines(),
+                "html" => trace.to_html(),
+                _ => bail!(usage),
+            };
+
+            File::create(file)
+                .and_then(|mut f| f.write_all(out.as_bytes()))
+                .map_err(|e| format!("Could not write {}: {}", file, e))?;
+            println!("Wrote {} step(s) to {}", trace.steps.len(), file);
+        }
+        _ => bail!("Unknown command {}", cmd),
+    }
+
+    Ok(())
+}
+
 fn actually(logger: &Logger) -> io::Result<()> {
     let mut input = String::new();
     let i = stdin();
+    let mut debugger = None;
+    let mut breakpoints = Vec::new();
+    let mut proof = None;
 
     loop {
         try!(i.read_line(&mut input));
 
-        {
+        if input.trim_left().starts_with(':') {
+            if let Err(e) = command(&input, &mut debugger, &mut breakpoints, &mut proof, logger) {
+                println!("Error: {}", e);
+            }
+        } else {
             let thing = inner(&input, logger);
             match thing {
                 Ok((ast, val, ty)) => {
@@ -0,0 +1,331 @@
+//! Locally nameless terms: variables bound inside the term are de Bruijn
+//! indices, free variables keep their names.
+//!
+//! Passes move under a binder by `open`ing its body with fresh names and
+//! `close` it again on the way out, so they never have to shift indices.
+//! Indices are counted like in `core::Term`: `BVar(1)` is the last binder of
+//! the innermost enclosing `Abs`.
+
+use std::collections::BTreeSet;
+
+use core::{self, Ty};
+use errors::*;
+use toplevel::Term as TTerm;
+use typecheck::{TypeCheckVisitor, TypeEnv};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Term {
+    BVar(u32),
+    FVar(String),
+    Abs(Vec<Ty>, Box<Term>),
+    App(Box<Term>, Vec<Term>),
+    True,
+    False,
+    Int(i64),
+    Not,
+    If(Box<Term>, Box<Term>, Box<Term>),
+    Stuck,
+    Hole(String),
+}
+
+impl Term {
+    /// Converts a named term. Variables not bound inside `t` become free
+    /// variables, so `t` does not have to be closed.
+    pub fn from_named(t: &TTerm) -> Result<Term> {
+        Ok(match *t {
+            TTerm::Var(ref x) => Term::FVar(x.clone()),
+            TTerm::Abs(ref args, ref body) => {
+                let names: Vec<&str> = args.iter().map(|&(ref x, _)| x.as_str()).collect();
+                let tys: Vec<Ty> = args.iter().map(|&(_, ref ty)| ty.clone()).collect();
+                Term::Abs(tys, Box::new(Term::from_named(body)?.close(&names)))
+            }
+            TTerm::App(ref f, ref args) => {
+                Term::App(Box::new(Term::from_named(f)?),
+                          args.iter().map(Term::from_named).collect::<Result<_>>()?)
+            }
+            TTerm::Int(n) => Term::Int(n),
+            TTerm::True => Term::True,
+            TTerm::False => Term::False,
+            TTerm::Not => Term::Not,
+            TTerm::If(ref c, ref b1, ref b2) => {
+                Term::If(Box::new(Term::from_named(c)?),
+                         Box::new(Term::from_named(b1)?),
+                         Box::new(Term::from_named(b2)?))
+            }
+            TTerm::Bottom => Term::Stuck,
+            TTerm::Hole(ref x) => Term::Hole(x.clone()),
+            TTerm::Error => return Err(rename_err("cannot rename a term with syntax errors")),
+        })
+    }
+
+    /// Converts to `core::Term`, numbering free variables by their position
+    /// in `ctx` (innermost last) as if `ctx` were enclosing binders.
+    pub fn to_core(&self, ctx: &[&str]) -> Result<core::Term> {
+        fn go(t: &Term, ctx: &[&str], depth: u32) -> Result<core::Term> {
+            Ok(match *t {
+                Term::BVar(k) => core::Term::Var(k),
+                Term::FVar(ref x) => {
+                    match ctx.iter().rposition(|y| y == x) {
+                        Some(i) => core::Term::Var(depth + (ctx.len() - i) as u32),
+                        None => bail!("Unknown variable {}", x),
+                    }
+                }
+                Term::Abs(ref tys, ref body) => {
+                    core::Term::abs(tys, go(body, ctx, depth + tys.len() as u32)?)
+                }
+                Term::App(ref f, ref args) => {
+                    core::Term::App(Box::new(go(f, ctx, depth)?),
+                                    args.iter()
+                                        .map(|a| go(a, ctx, depth))
+                                        .collect::<Result<_>>()?)
+                }
+                Term::True => core::Term::True,
+                Term::False => core::Term::False,
+                Term::Int(n) => core::Term::Int(n),
+                Term::Not => core::Term::Not,
+                Term::If(ref c, ref b1, ref b2) => {
+                    core::Term::if_(go(c, ctx, depth)?, go(b1, ctx, depth)?, go(b2, ctx, depth)?)
+                }
+                Term::Stuck => core::Term::Stuck,
+                Term::Hole(ref x) => core::Term::Hole(x.clone()),
+            })
+        }
+
+        go(self, ctx, 0)
+    }
+
+    /// Rebuilds a term below `depth` binders, replacing variables with `var`.
+    fn map_vars<F>(&self, depth: u32, var: &F) -> Term
+        where F: Fn(&Term, u32) -> Option<Term>
+    {
+        if let Some(t) = var(self, depth) {
+            return t;
+        }
+
+        match *self {
+            Term::Abs(ref tys, ref body) => {
+                Term::Abs(tys.clone(),
+                          Box::new(body.map_vars(depth + tys.len() as u32, var)))
+            }
+            Term::App(ref f, ref args) => {
+                Term::App(Box::new(f.map_vars(depth, var)),
+                          args.iter().map(|a| a.map_vars(depth, var)).collect())
+            }
+            Term::If(ref c, ref b1, ref b2) => {
+                Term::If(Box::new(c.map_vars(depth, var)),
+                         Box::new(b1.map_vars(depth, var)),
+                         Box::new(b2.map_vars(depth, var)))
+            }
+            ref t => t.clone(),
+        }
+    }
+
+    /// Replaces the indices of an abstraction's binders by `names`, turning
+    /// its body into a term with those names free.
+    pub fn open(&self, names: &[&str]) -> Term {
+        let n = names.len() as u32;
+
+        self.map_vars(0,
+                      &|t, depth| match *t {
+                          Term::BVar(k) if k > depth && k <= depth + n => {
+                              Some(Term::FVar(names[(n - (k - depth)) as usize].into()))
+                          }
+                          _ => None,
+                      })
+    }
+
+    /// The inverse of `open`: binds the free variables `names`, the last
+    /// name becoming index 1. Later names shadow earlier ones.
+    pub fn close(&self, names: &[&str]) -> Term {
+        let n = names.len() as u32;
+
+        self.map_vars(0,
+                      &|t, depth| match *t {
+                          Term::FVar(ref x) => {
+                              names.iter()
+                                  .rposition(|y| y == x)
+                                  .map(|i| Term::BVar(depth + n - i as u32))
+                          }
+                          _ => None,
+                      })
+    }
+
+    /// Replaces the free variable `x` by `u`.
+    pub fn subst(&self, x: &str, u: &Term) -> Term {
+        self.map_vars(0,
+                      &|t, _| match *t {
+                          Term::FVar(ref y) if y == x => Some(u.clone()),
+                          _ => None,
+                      })
+    }
+
+    pub fn free_vars(&self) -> BTreeSet<String> {
+        fn go(t: &Term, fvs: &mut BTreeSet<String>) {
+            match *t {
+                Term::FVar(ref x) => {
+                    fvs.insert(x.clone());
+                }
+                Term::Abs(_, ref body) => go(body, fvs),
+                Term::App(ref f, ref args) => {
+                    go(f, fvs);
+                    for a in args {
+                        go(a, fvs);
+                    }
+                }
+                Term::If(ref c, ref b1, ref b2) => {
+                    go(c, fvs);
+                    go(b1, fvs);
+                    go(b2, fvs);
+                }
+                _ => {}
+            }
+        }
+
+        let mut fvs = BTreeSet::new();
+        go(self, &mut fvs);
+        fvs
+    }
+
+    /// True if every index refers to a binder inside the term.
+    pub fn is_locally_closed(&self) -> bool {
+        fn go(t: &Term, depth: u32) -> bool {
+            match *t {
+                Term::BVar(k) => k >= 1 && k <= depth,
+                Term::Abs(ref tys, ref body) => go(body, depth + tys.len() as u32),
+                Term::App(ref f, ref args) => go(f, depth) && args.iter().all(|a| go(a, depth)),
+                Term::If(ref c, ref b1, ref b2) => go(c, depth) && go(b1, depth) && go(b2, depth),
+                _ => true,
+            }
+        }
+
+        go(self, 0)
+    }
+
+    pub fn unparse(&self) -> String {
+        match *self {
+            Term::BVar(k) => k.to_string(),
+            Term::FVar(ref x) => x.clone(),
+            Term::Abs(ref tys, ref body) => {
+                let tys: Vec<String> = tys.iter().map(|ty| ty.unparse()).collect();
+                format!("(/lam {}. {})", tys.join(", "), body.unparse())
+            }
+            Term::App(ref f, ref args) => {
+                let args: Vec<String> = args.iter().map(|a| a.unparse()).collect();
+                format!("({} {})", f.unparse(), args.join(" "))
+            }
+            Term::True => "#T".into(),
+            Term::False => "#F".into(),
+            Term::Int(n) => n.to_string(),
+            Term::Not => "!".into(),
+            Term::If(ref c, ref b1, ref b2) => {
+                format!("(if {} {} {})", c.unparse(), b1.unparse(), b2.unparse())
+            }
+            Term::Stuck => "_|_".into(),
+            Term::Hole(ref x) => format!("?{}", x),
+        }
+    }
+}
+
+/// Typechecks locally closed terms against a context of named free variables.
+pub struct TypeChecker {
+    ctx: Vec<(String, Ty)>,
+}
+
+impl TypeChecker {
+    /// Later entries of `ctx` shadow earlier ones.
+    pub fn new(ctx: &[(&str, Ty)]) -> TypeChecker {
+        TypeChecker { ctx: ctx.iter().map(|&(x, ref ty)| (x.into(), ty.clone())).collect() }
+    }
+
+    /// Lowers `t` with the context as its enclosing binders and checks it
+    /// with `TypeCheckVisitor`, so both follow the same rules.
+    pub fn type_of(&mut self, t: &Term) -> Result<Ty> {
+        if !t.is_locally_closed() {
+            bail!("Unopened bound variable in {}", t.unparse());
+        }
+
+        let names: Vec<&str> = self.ctx.iter().map(|&(ref x, _)| x.as_str()).collect();
+        let gamma: TypeEnv = self.ctx.iter().map(|&(_, ref ty)| ty.clone()).collect();
+        let t = t.to_core(&names)?;
+        Ok(TypeCheckVisitor::with_context(gamma).type_of(&t)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::TyLit;
+    use corpus::{get, PROGRAMS};
+    use lispy;
+
+    fn named(s: &str) -> Term {
+        Term::from_named(&lispy::get_code(s.as_bytes()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_from_named() {
+        let t = named("(/lam x: #B, y: #Int. (if x (f y) (/lam x: #Int. (g x y))))");
+        assert_eq!(t.unparse(),
+                   "(/lam #B, #Int. (if 2 (f 1) (/lam #Int. (g 1 2))))");
+        assert!(t.is_locally_closed());
+        assert_eq!(t.free_vars().into_iter().collect::<Vec<_>>(), vec!["f", "g"]);
+
+        for code in PROGRAMS {
+            assert_eq!(named(code).to_core(&[]).unwrap(), get(code), "{}", code);
+        }
+
+        let partial = lispy::scan::parse_recovering(lispy::tokenize::scan(b"(f (g :))").unwrap());
+        assert!(Term::from_named(&partial.term).is_err());
+    }
+
+    #[test]
+    fn test_open_close() {
+        let t = named("(/lam x: #B, y: #Int. (if x (f y) (/lam x: #Int. (g x y))))");
+        let (tys, body) = match t {
+            Term::Abs(ref tys, ref body) => (tys.clone(), body.as_ref().clone()),
+            _ => unreachable!(),
+        };
+
+        let opened = body.open(&["a", "b"]);
+        assert_eq!(opened.unparse(), "(if a (f b) (/lam #Int. (g 1 b)))");
+        assert_eq!(opened.close(&["a", "b"]), body);
+        assert_eq!(Term::Abs(tys, Box::new(opened.close(&["a", "b"]))), t);
+
+        assert_eq!(opened.subst("b", &Term::Int(3)).unparse(),
+                   "(if a (f 3) (/lam #Int. (g 1 3)))");
+        assert!(!body.is_locally_closed());
+    }
+
+    #[test]
+    fn test_open_terms() {
+        let b: Ty = TyLit::Bool.into();
+        let i: Ty = TyLit::Int.into();
+        let ctx = [("f", Ty::arrow(&[b.clone()], i.clone())), ("x", b.clone())];
+
+        let t = named("(/lam y: #B. (if y (f x) (f y)))");
+        assert_eq!(TypeChecker::new(&ctx).type_of(&t).unwrap(), Ty::arrow(&[b.clone()], i));
+        assert!(TypeChecker::new(&ctx[..1]).type_of(&t).is_err());
+        assert!(TypeChecker::new(&[("x", b)]).type_of(&named("(/lam x: #Int. (! x))")).is_err());
+        let e = TypeChecker::new(&ctx).type_of(&named("(f x x)")).unwrap_err();
+        assert_eq!(e.to_string(),
+                   "type error: (2 1 1) expects 1 argument(s) but was given 2 \
+                    (context 2: (#B -> #Int), 1: #B)");
+        assert!(TypeChecker::new(&[]).type_of(&Term::BVar(1)).is_err());
+
+        assert_eq!(t.to_core(&["f", "x"]).unwrap(),
+                   core::Term::abs(&[TyLit::Bool.into()],
+                                   core::Term::if_(core::Term::Var(1),
+                                                   core::Term::app(core::Term::Var(3),
+                                                                   &[core::Term::Var(2)]),
+                                                   core::Term::app(core::Term::Var(3),
+                                                                   &[core::Term::Var(1)]))));
+        assert!(t.to_core(&["f"]).is_err());
+
+        for code in PROGRAMS {
+            assert_eq!(TypeChecker::new(&[]).type_of(&named(code)).unwrap(),
+                       TypeCheckVisitor::new().type_of(&get(code)).unwrap(),
+                       "{}",
+                       code);
+        }
+    }
+}
@@ -0,0 +1,344 @@
+//! Type-preserving optimizations over `core::Term`.
+//!
+//! * constant folding of `!` and `if` on literal booleans (the language has
+//!   no arithmetic primitives yet, so there is nothing else to fold),
+//! * beta-inlining of applications whose lambda is small or uses each
+//!   parameter at most once,
+//! * dead-code removal of arguments whose parameter is never used.
+//!
+//! Inlining and dead-code removal only fire when the arguments involved are
+//! values, so call-by-value evaluation order and stuckness are preserved.
+
+use std::fmt;
+
+use core::Term;
+use eval;
+
+pub struct Config {
+    pub fold: bool,
+    pub inline: bool,
+    pub dead_code: bool,
+    /// Lambdas whose body has at most this many nodes are always inlined.
+    pub inline_size: usize,
+    /// Upper bound on the number of rounds of all passes.
+    pub max_iterations: usize,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            fold: true,
+            inline: true,
+            dead_code: true,
+            inline_size: 8,
+            max_iterations: 16,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Stats {
+    pub folded: usize,
+    pub inlined: usize,
+    pub dead: usize,
+    pub iterations: usize,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{} folded, {} inlined, {} dead argument(s) removed in {} round(s)",
+               self.folded,
+               self.inlined,
+               self.dead,
+               self.iterations)
+    }
+}
+
+/// Number of nodes in a term.
+pub fn size(t: &Term) -> usize {
+    match *t {
+        Term::Abs(_, ref body) => 1 + size(body),
+        Term::App(ref f, ref args) => 1 + size(f) + args.iter().map(size).sum::<usize>(),
+        Term::If(ref c, ref b1, ref b2) => 1 + size(c) + size(b1) + size(b2),
+        _ => 1,
+    }
+}
+
+/// Number of occurrences of the `j`-th innermost binder of `t`'s context.
+fn uses(t: &Term, j: u32, depth: u32) -> usize {
+    match *t {
+        Term::Var(k) if k == j + depth => 1,
+        Term::Abs(ref tys, ref body) => uses(body, j, depth + tys.len() as u32),
+        Term::App(ref f, ref args) => {
+            uses(f, j, depth) + args.iter().map(|a| uses(a, j, depth)).sum::<usize>()
+        }
+        Term::If(ref c, ref b1, ref b2) => {
+            uses(c, j, depth) + uses(b1, j, depth) + uses(b2, j, depth)
+        }
+        _ => 0,
+    }
+}
+
+/// Removes the unused `j`-th innermost binder from the context of `t`.
+fn remove_binder(t: &Term, j: u32, depth: u32) -> Term {
+    match *t {
+        Term::Var(k) if k > j + depth => Term::Var(k - 1),
+        Term::Abs(ref tys, ref body) => {
+            Term::abs(tys, remove_binder(body, j, depth + tys.len() as u32))
+        }
+        Term::App(ref f, ref args) => {
+            Term::App(Box::new(remove_binder(f, j, depth)),
+                      args.iter().map(|a| remove_binder(a, j, depth)).collect())
+        }
+        Term::If(ref c, ref b1, ref b2) => {
+            Term::if_(remove_binder(c, j, depth),
+                      remove_binder(b1, j, depth),
+                      remove_binder(b2, j, depth))
+        }
+        ref t => t.clone(),
+    }
+}
+
+pub struct Optimizer {
+    config: Config,
+    stats: Stats,
+}
+
+impl Optimizer {
+    pub fn new(config: Config) -> Optimizer {
+        Optimizer {
+            config: config,
+            stats: Stats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Runs the enabled passes until nothing changes or the iteration limit is hit.
+    pub fn optimize(&mut self, t: &Term) -> Term {
+        self.stats = Stats::default();
+        let mut t = t.clone();
+
+        while self.stats.iterations < self.config.max_iterations {
+            self.stats.iterations += 1;
+            let before = self.stats;
+
+            if self.config.fold {
+                t = self.fold(&t);
+            }
+            if self.config.inline {
+                t = self.inline(&t);
+            }
+            if self.config.dead_code {
+                t = self.dead_code(&t);
+            }
+
+            if self.stats.folded == before.folded && self.stats.inlined == before.inlined &&
+               self.stats.dead == before.dead {
+                break;
+            }
+        }
+
+        t
+    }
+
+    /// Rebuilds `t` bottom-up, applying `rewrite` to every node.
+    fn bottom_up<F>(&mut self, t: &Term, rewrite: &F) -> Term
+        where F: Fn(&mut Self, Term) -> Term
+    {
+        let t = match *t {
+            Term::Abs(ref tys, ref body) => Term::abs(tys, self.bottom_up(body, rewrite)),
+            Term::App(ref f, ref args) => {
+                let f = self.bottom_up(f, rewrite);
+                let args = args.iter().map(|a| self.bottom_up(a, rewrite)).collect();
+                Term::App(Box::new(f), args)
+            }
+            Term::If(ref c, ref b1, ref b2) => {
+                Term::if_(self.bottom_up(c, rewrite),
+                          self.bottom_up(b1, rewrite),
+                          self.bottom_up(b2, rewrite))
+            }
+            ref t => t.clone(),
+        };
+
+        rewrite(self, t)
+    }
+
+    fn fold(&mut self, t: &Term) -> Term {
+        self.bottom_up(t,
+                       &|s: &mut Self, t: Term| {
+            let folded = match t {
+                Term::App(ref f, ref args) if **f == Term::Not && args.len() == 1 => {
+                    match args[0] {
+                        Term::True => Some(Term::False),
+                        Term::False => Some(Term::True),
+                        _ => None,
+                    }
+                }
+                Term::If(ref c, ref b1, ref b2) => {
+                    match **c {
+                        Term::True => Some(b1.as_ref().clone()),
+                        Term::False => Some(b2.as_ref().clone()),
+                        Term::App(ref f, ref args) if **f == Term::Not && args.len() == 1 => {
+                            Some(Term::if_(args[0].clone(), b2.as_ref().clone(), b1.as_ref().clone()))
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+
+            match folded {
+                Some(t) => {
+                    s.stats.folded += 1;
+                    t
+                }
+                None => t,
+            }
+        })
+    }
+
+    fn inline(&mut self, t: &Term) -> Term {
+        let inline_size = self.config.inline_size;
+
+        self.bottom_up(t,
+                       &|s: &mut Self, t: Term| {
+            let inlined = match t {
+                Term::App(ref f, ref args) if args.iter().all(Term::is_val) => {
+                    match **f {
+                        Term::Abs(ref tys, ref body) if tys.len() == args.len() => {
+                            let n = tys.len() as u32;
+                            let linear = (1..n + 1).all(|j| uses(body, j, 0) <= 1);
+
+                            if linear || size(body) <= inline_size {
+                                Some(eval::beta(body, args))
+                            } else {
+                                None
+                            }
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+
+            match inlined {
+                Some(t) => {
+                    s.stats.inlined += 1;
+                    t
+                }
+                None => t,
+            }
+        })
+    }
+
+    fn dead_code(&mut self, t: &Term) -> Term {
+        self.bottom_up(t,
+                       &|s: &mut Self, t: Term| {
+            let (tys, mut body, args) = match t {
+                Term::App(ref f, ref args) => {
+                    match **f {
+                        Term::Abs(ref tys, ref body) if tys.len() == args.len() => {
+                            (tys.clone(), body.as_ref().clone(), args.clone())
+                        }
+                        _ => return t.clone(),
+                    }
+                }
+                _ => return t,
+            };
+
+            let n = tys.len();
+            let (mut keep_tys, mut keep_args) = (Vec::new(), Vec::new());
+            // Walk from the outermost parameter: removing a binder only renumbers
+            // the ones outside it, which have already been checked.
+            for (p, (ty, arg)) in tys.into_iter().zip(args).enumerate() {
+                let j = (n - p) as u32;
+                if uses(&body, j, 0) == 0 && arg.is_val() {
+                    body = remove_binder(&body, j, 0);
+                    s.stats.dead += 1;
+                } else {
+                    keep_tys.push(ty);
+                    keep_args.push(arg);
+                }
+            }
+
+            if keep_tys.is_empty() {
+                body
+            } else {
+                Term::App(Box::new(Term::abs(&keep_tys, body)), keep_args)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use slog::{Discard, Logger};
+
+    use super::*;
+    use corpus::{get, PROGRAMS};
+    use eval::Evaluator;
+    use typecheck::TypeCheckVisitor;
+
+    #[test]
+    fn test_fold() {
+        let mut opt = Optimizer::new(Config::default());
+
+        assert_eq!(opt.optimize(&get("(if #T 1 2)")), Term::Int(1));
+        assert_eq!(opt.optimize(&get("(! #F)")), Term::True);
+        assert_eq!(opt.optimize(&get("(/lam b: #B. (if (! b) 1 2))")),
+                   get("(/lam b: #B. (if b 2 1))"));
+        assert_eq!(opt.stats().folded, 1);
+    }
+
+    #[test]
+    fn test_inline_and_dead_code() {
+        let mut opt = Optimizer::new(Config {
+                                         inline: false,
+                                         ..Config::default()
+                                     });
+        assert_eq!(opt.optimize(&get("((/lam x: #B, y: #Int. 5) #T 3)")), Term::Int(5));
+        assert_eq!(opt.stats().dead, 2);
+        assert_eq!(opt.optimize(&get("((/lam x: #B, y: #Int. y) #T 3)")),
+                   get("((/lam y: #Int. y) 3)"));
+        assert_eq!(opt.stats().dead, 1);
+
+        // Arguments that are not values are kept so evaluation can still get stuck on them.
+        let mut opt = Optimizer::new(Config {
+                                         fold: false,
+                                         inline: false,
+                                         ..Config::default()
+                                     });
+        let t = get("((/lam x: #B, y: #Int. y) (! #T) 3)");
+        assert_eq!(opt.optimize(&t), t);
+
+        let mut opt = Optimizer::new(Config {
+                                         inline_size: 0,
+                                         ..Config::default()
+                                     });
+        assert_eq!(opt.optimize(&get("(((/lam x: #Int. (/lam y: #B. x)) 1) #F)")),
+                   Term::Int(1));
+        assert!(opt.stats().inlined >= 1);
+    }
+
+    #[test]
+    fn test_preserves_types() {
+        let mut ev = Evaluator::new(&Logger::root(Discard, o!()));
+        let mut opt = Optimizer::new(Config::default());
+
+        for code in PROGRAMS {
+            let t = get(code);
+            let o = opt.optimize(&t);
+
+            assert_eq!(TypeCheckVisitor::new().type_of(&o).unwrap(),
+                       TypeCheckVisitor::new().type_of(&t).unwrap(),
+                       "{}",
+                       code);
+            assert_eq!(ev.eval(&o).unwrap(), ev.eval(&t).unwrap(), "{}", code);
+            assert!(size(&o) <= size(&t), "{}", code);
+        }
+    }
+}
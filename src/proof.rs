@@ -0,0 +1,355 @@
+//! Proving propositions by building terms, one tactic at a time.
+//!
+//! Under Curry-Howard a type is a proposition and a closed term of it is a
+//! proof. A `Proof` holds a partial term whose holes are the open goals;
+//! each tactic fills the first goal with a term that may contain new holes,
+//! which become the next goals. The proof is complete when no holes remain.
+
+use std::fmt;
+
+use core::{Ty, TyLit};
+use debrujin::{name_term_in, var_name, RenameVisitor};
+use errors::*;
+use span::{self, Spans};
+use synth;
+use toplevel::Term;
+use typecheck::TypeCheckVisitor;
+
+/// A hole of the partial proof, with the hypotheses in scope there.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Goal {
+    pub name: String,
+    /// Outermost first.
+    pub context: Vec<(String, Ty)>,
+    pub ty: Ty,
+}
+
+impl fmt::Display for Goal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &(ref x, ref ty) in &self.context {
+            writeln!(f, "  {} : {}", x, ty.to_prop())?;
+        }
+        write!(f, "  ----------\n  {}", self.ty.to_prop())
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct State {
+    term: Term,
+    goals: Vec<Goal>,
+    next: usize,
+}
+
+pub struct Proof {
+    ty: Ty,
+    state: State,
+    history: Vec<State>,
+}
+
+/// The type of `t` in `context`, where `t` may not contain holes.
+fn type_in(context: &[(String, Ty)], t: &Term) -> Result<Ty> {
+    let closed = if context.is_empty() {
+        t.clone()
+    } else {
+        Term::abss(context, t.clone())
+    };
+    span::check_closed(&closed, &Spans::new())?;
+    let ast = RenameVisitor::new().rename_term(&closed)?;
+    if !ast.holes().is_empty() {
+        bail!("Tactics make the holes, {} cannot contain any", t.pretty());
+    }
+
+    match TypeCheckVisitor::new().type_of(&ast)? {
+        Ty::Arrow(_, res) if !context.is_empty() => Ok(*res),
+        ty => Ok(ty),
+    }
+}
+
+/// Replaces the hole `name` in `t` with `with`.
+fn fill(t: &mut Term, name: &str, with: &Term) -> bool {
+    match *t {
+        Term::Hole(ref x) if x == name => {}
+        Term::Abs(_, ref mut body) => return fill(body, name, with),
+        Term::App(ref mut f, ref mut args) => {
+            return fill(f, name, with) || args.iter_mut().any(|a| fill(a, name, with))
+        }
+        Term::If(ref mut c, ref mut b1, ref mut b2) => {
+            return fill(c, name, with) || fill(b1, name, with) || fill(b2, name, with)
+        }
+        _ => return false,
+    }
+
+    *t = with.clone();
+    true
+}
+
+impl Proof {
+    pub fn new(ty: &Ty) -> Proof {
+        let goal = Goal {
+            name: "g1".into(),
+            context: Vec::new(),
+            ty: ty.clone(),
+        };
+
+        Proof {
+            ty: ty.clone(),
+            state: State {
+                term: Term::Hole(goal.name.clone()),
+                goals: vec![goal],
+                next: 2,
+            },
+            history: Vec::new(),
+        }
+    }
+
+    /// The proposition being proved.
+    pub fn ty(&self) -> &Ty {
+        &self.ty
+    }
+
+    /// The proof so far, with a hole for each open goal.
+    pub fn term(&self) -> &Term {
+        &self.state.term
+    }
+
+    pub fn goals(&self) -> &[Goal] {
+        &self.state.goals
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.state.goals.is_empty()
+    }
+
+    fn goal(&self) -> Result<&Goal> {
+        self.state.goals.first().ok_or("No goals left, the proof is complete".into())
+    }
+
+    fn new_goal(&mut self, context: Vec<(String, Ty)>, ty: Ty) -> Goal {
+        self.state.next += 1;
+        Goal {
+            name: format!("g{}", self.state.next - 1),
+            context: context,
+            ty: ty,
+        }
+    }
+
+    /// Fills the first goal with `t`, whose holes are `goals`.
+    fn refine(&mut self, saved: State, t: Term, goals: Vec<Goal>) {
+        let name = self.state.goals.remove(0).name;
+        fill(&mut self.state.term, &name, &t);
+
+        let rest = self.state.goals.split_off(0);
+        self.state.goals = goals;
+        self.state.goals.extend(rest);
+        self.history.push(saved);
+    }
+
+    /// Assumes the premises of an implication, naming them `names` or, if
+    /// none are given, fresh names.
+    pub fn intro(&mut self, names: &[&str]) -> Result<()> {
+        let goal = self.goal()?.clone();
+        let (params, res) = match goal.ty {
+            Ty::Arrow(ref params, ref res) => (params, res),
+            ref ty => bail!("{} is not an implication", ty.to_prop()),
+        };
+        if !names.is_empty() && names.len() != params.len() {
+            bail!("{} has {} premise(s), got {} name(s)", goal.ty.to_prop(), params.len(), names.len());
+        }
+
+        let mut context = goal.context.clone();
+        let mut args = Vec::new();
+        for (i, p) in params.iter().enumerate() {
+            let x = match names.get(i) {
+                Some(x) => (*x).to_string(),
+                None => {
+                    (0..)
+                        .map(var_name)
+                        .find(|x| context.iter().all(|&(ref y, _)| x != y))
+                        .unwrap()
+                }
+            };
+            if context.iter().any(|&(ref y, _)| *y == x) {
+                bail!("{} is already a hypothesis", x);
+            }
+            context.push((x.clone(), p.clone()));
+            args.push((x, p.clone()));
+        }
+
+        let saved = self.state.clone();
+        let g = self.new_goal(context, (**res).clone());
+        let t = Term::abss(&args, Term::Hole(g.name.clone()));
+        self.refine(saved, t, vec![g]);
+        Ok(())
+    }
+
+    /// Proves the goal with `t`, which must have exactly its type.
+    pub fn exact(&mut self, t: &Term) -> Result<()> {
+        let goal = self.goal()?.clone();
+        let ty = type_in(&goal.context, t)?;
+        if ty != goal.ty {
+            bail!("{} proves {}, not {}", t.pretty(), ty.to_prop(), goal.ty.to_prop());
+        }
+
+        let saved = self.state.clone();
+        self.refine(saved, t.clone(), Vec::new());
+        Ok(())
+    }
+
+    /// Proves the goal by applying `t`, an implication that eventually
+    /// concludes it. Each premise becomes a new goal.
+    pub fn apply(&mut self, t: &Term) -> Result<()> {
+        let goal = self.goal()?.clone();
+        let saved = self.state.clone();
+
+        let mut ty = type_in(&goal.context, t)?;
+        let mut app = t.clone();
+        let mut goals = Vec::new();
+        while ty != goal.ty {
+            let (params, res) = match ty {
+                Ty::Arrow(params, res) => (params, res),
+                _ => {
+                    self.state = saved;
+                    bail!("{} does not conclude {}", t.pretty(), goal.ty.to_prop());
+                }
+            };
+
+            let mut args = Vec::new();
+            for p in params {
+                let g = self.new_goal(goal.context.clone(), p);
+                args.push(Term::Hole(g.name.clone()));
+                goals.push(g);
+            }
+            app = Term::App(Box::new(app), args);
+            ty = *res;
+        }
+
+        self.refine(saved, app, goals);
+        Ok(())
+    }
+
+    /// Splits the goal on a boolean `t`, one goal for each value.
+    pub fn cases(&mut self, t: &Term) -> Result<()> {
+        let goal = self.goal()?.clone();
+        let ty = type_in(&goal.context, t)?;
+        if ty != TyLit::Bool.into() {
+            bail!("Can only split on Bool, {} is {}", t.pretty(), ty.to_prop());
+        }
+
+        let saved = self.state.clone();
+        let g1 = self.new_goal(goal.context.clone(), goal.ty.clone());
+        let g2 = self.new_goal(goal.context.clone(), goal.ty.clone());
+        let t = Term::if_(t.clone(),
+                          Term::Hole(g1.name.clone()),
+                          Term::Hole(g2.name.clone()));
+        self.refine(saved, t, vec![g1, g2]);
+        Ok(())
+    }
+
+    /// Proves the goal with a term found by `synth`, nesting at most `depth`.
+    pub fn auto(&mut self, depth: usize) -> Result<()> {
+        let goal = self.goal()?.clone();
+        let gamma: Vec<Ty> = goal.context.iter().map(|&(_, ref ty)| ty.clone()).collect();
+        let names: Vec<String> = goal.context.iter().map(|&(ref x, _)| x.clone()).collect();
+
+        let t = match synth::synthesize_in(&gamma, &goal.ty, depth) {
+            Some(t) => name_term_in(&t, &names)?,
+            None => bail!("No proof of {} up to depth {}", goal.ty.to_prop(), depth),
+        };
+
+        let saved = self.state.clone();
+        self.refine(saved, t, Vec::new());
+        Ok(())
+    }
+
+    /// Takes back the last tactic.
+    pub fn undo(&mut self) -> Result<()> {
+        self.state = self.history.pop().ok_or("Nothing to undo")?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Proof {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let goals = &self.state.goals;
+        if goals.is_empty() {
+            return write!(f, "Proved {}\n  {}", self.ty.to_prop(), self.state.term.pretty());
+        }
+
+        writeln!(f, "Goal ?{} (1 of {})\n{}", goals[0].name, goals.len(), goals[0])?;
+        for g in &goals[1..] {
+            writeln!(f, "Goal ?{}: {}", g.name, g.ty.to_prop())?;
+        }
+        write!(f, "Proof: {}", self.state.term.pretty())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lispy;
+
+    fn ty(s: &str) -> Ty {
+        lispy::get_type(s.as_bytes()).unwrap()
+    }
+
+    fn term(s: &str) -> Term {
+        lispy::get_code(s.as_bytes()).unwrap()
+    }
+
+    fn check(p: &Proof) {
+        let ast = RenameVisitor::new().rename_term(p.term()).unwrap();
+        assert_eq!(&TypeCheckVisitor::new().type_of(&ast).unwrap(), p.ty());
+    }
+
+    #[test]
+    fn test_to_prop() {
+        assert_eq!(ty("((#B -> #Int), #B -> #Int)").to_prop(), "(Bool → Int) ∧ Bool → Int");
+        assert_eq!(ty("(#B -> (#Int -> #B))").to_prop(), "Bool → Int → Bool");
+        assert_eq!(Ty::arrow(&[TyLit::Bool.into()], Ty::Bottom).to_prop(), "Bool → ⊥");
+    }
+
+    #[test]
+    fn test_tactics() {
+        // Modus ponens.
+        let mut p = Proof::new(&ty("((#B -> #Int), #B -> #Int)"));
+        p.intro(&["f", "b"]).unwrap();
+        assert_eq!(p.goals()[0].context.len(), 2);
+        assert!(p.exact(&term("b")).is_err());
+        p.apply(&term("f")).unwrap();
+        assert_eq!(p.goals()[0].ty, TyLit::Bool.into());
+        assert_eq!(p.term().pretty(), "(/lam f: (#B -> #Int), b: #B. (f ?g3))");
+        p.exact(&term("b")).unwrap();
+        assert!(p.is_complete());
+        check(&p);
+        assert_eq!(p.to_string(),
+                   "Proved (Bool → Int) ∧ Bool → Int\n  (/lam f: (#B -> #Int), b: #B. (f b))");
+
+        p.undo().unwrap();
+        assert_eq!(p.goals().len(), 1);
+        p.auto(3).unwrap();
+        check(&p);
+
+        let mut p = Proof::new(&ty("(#B -> (#B -> #B))"));
+        p.intro(&[]).unwrap();
+        p.intro(&[]).unwrap();
+        p.cases(&term("x")).unwrap();
+        assert_eq!(p.goals().len(), 2);
+        p.exact(&term("y")).unwrap();
+        p.exact(&term("(! y)")).unwrap();
+        assert_eq!(p.term().pretty(), "(/lam x: #B. (/lam y: #B. (if x y (! y))))");
+        check(&p);
+
+        let mut p = Proof::new(&ty("(#Int -> #B)"));
+        assert!(p.intro(&["a", "b"]).is_err());
+        assert!(p.apply(&term("#T")).is_err());
+        assert!(p.cases(&term("1")).is_err());
+        assert_eq!(p.goals()[0].name, "g1");
+        p.intro(&["n"]).unwrap();
+        assert!(p.exact(&term("?h")).is_err());
+        assert!(p.exact(&term("m")).is_err());
+        assert!(p.intro(&[]).is_err());
+        p.auto(0).unwrap();
+        check(&p);
+        assert!(p.auto(0).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+//! Source positions and rendered diagnostics.
+//!
+//! Terms do not carry positions themselves. Instead the parser returns a
+//! `Spans` side table from node paths (numbered as in `eval::redex`) to source
+//! ranges. Renaming to `core::Term` keeps the shape of the term, so the same
+//! table locates nodes of the renamed term.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use errors::Error;
+use toplevel::Term;
+
+/// A byte range of the source, with the line and column (both from 1) it starts at.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Span {
+    pub lo: usize,
+    pub hi: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Span {
+    pub fn new(src: &str, lo: usize, hi: usize) -> Span {
+        let before = &src[..lo];
+        let line = before.matches('\n').count() + 1;
+        let col = lo - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+
+        Span {
+            lo: lo,
+            hi: hi,
+            line: line,
+            col: col,
+        }
+    }
+
+    /// Like `new` for a span starting at or after `prev`, only scanning the
+    /// source in between, so spans made left to right cost linear time.
+    pub fn after(prev: Span, src: &str, lo: usize, hi: usize) -> Span {
+        let between = &src[prev.lo..lo];
+        let (line, col) = match between.rfind('\n') {
+            Some(i) => (prev.line + between.matches('\n').count(), lo - (prev.lo + i + 1) + 1),
+            None => (prev.line, prev.col + lo - prev.lo),
+        };
+
+        Span {
+            lo: lo,
+            hi: hi,
+            line: line,
+            col: col,
+        }
+    }
+
+    /// From the start of `self` to the end of `other`.
+    pub fn to(&self, other: Span) -> Span {
+        Span { hi: other.hi, ..*self }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Spans {
+    spans: HashMap<Vec<usize>, Span>,
+}
+
+impl Spans {
+    pub fn new() -> Spans {
+        Spans::default()
+    }
+
+    pub fn insert(&mut self, path: Vec<usize>, span: Span) {
+        self.spans.insert(path, span);
+    }
+
+    pub fn get(&self, path: &[usize]) -> Option<Span> {
+        self.spans.get(path).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Diagnostic {
+    pub fn new<S: Into<String>>(message: S, span: Option<Span>) -> Diagnostic {
+        Diagnostic {
+            message: message.into(),
+            span: span,
+        }
+    }
+
+    /// The message followed by the source line with the span underlined.
+    /// Spans over several lines are underlined up to the end of the first.
+    pub fn render(&self, src: &str) -> String {
+        let span = match self.span {
+            Some(span) => span,
+            None => return self.message.clone(),
+        };
+
+        let start = src[..span.lo].rfind('\n').map_or(0, |i| i + 1);
+        let end = src[span.lo..].find('\n').map_or(src.len(), |i| span.lo + i);
+        let line = &src[start..end];
+        let width = ::std::cmp::max(1, ::std::cmp::min(span.hi, end) - span.lo);
+        let gutter = span.line.to_string().len();
+
+        format!("{msg}\n{pad} --> {span}\n{pad} |\n{n} | {line}\n{pad} | {indent}{carets}",
+                msg = self.message,
+                pad = " ".repeat(gutter),
+                span = span,
+                n = span.line,
+                line = line,
+                indent = " ".repeat(src[start..span.lo].chars().count()),
+                carets = "^".repeat(width))
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{} at {}", self.message, span),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl From<Diagnostic> for Error {
+    fn from(d: Diagnostic) -> Error {
+        d.to_string().into()
+    }
+}
+
+/// Reports the first variable of `t` that is not bound by an enclosing lambda,
+/// or the first lambda that names two parameters the same. Renaming assumes
+/// neither happens.
+pub fn check_closed(t: &Term, spans: &Spans) -> Result<(), Diagnostic> {
+    fn go<'a>(t: &'a Term,
+              path: &mut Vec<usize>,
+              scope: &mut Vec<&'a str>,
+              spans: &Spans)
+              -> Result<(), Diagnostic> {
+        match *t {
+            Term::Var(ref x) if !scope.contains(&x.as_str()) => {
+                Err(Diagnostic::new(format!("Unbound variable {}", x), spans.get(path)))
+            }
+            Term::Abs(ref args, ref body) => {
+                for (i, &(ref x, _)) in args.iter().enumerate() {
+                    if args[..i].iter().any(|&(ref y, _)| x == y) {
+                        let msg = format!("Parameter {} is named twice", x);
+                        return Err(Diagnostic::new(msg, spans.get(path)));
+                    }
+                }
+
+                scope.extend(args.iter().map(|&(ref x, _)| x.as_str()));
+                path.push(0);
+                go(body, path, scope, spans)?;
+                path.pop();
+                let len = scope.len() - args.len();
+                scope.truncate(len);
+                Ok(())
+            }
+            Term::App(ref f, ref args) => {
+                path.push(0);
+                go(f, path, scope, spans)?;
+                for (i, a) in args.iter().enumerate() {
+                    *path.last_mut().unwrap() = i + 1;
+                    go(a, path, scope, spans)?;
+                }
+                path.pop();
+                Ok(())
+            }
+            Term::If(ref c, ref b1, ref b2) => {
+                for (i, t) in [c, b1, b2].iter().enumerate() {
+                    path.push(i);
+                    go(t, path, scope, spans)?;
+                    path.pop();
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    go(t, &mut Vec::new(), &mut Vec::new(), spans)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lispy;
+
+    #[test]
+    fn test_render() {
+        let src = "(if 1\n    #T ff)";
+        let d = Diagnostic::new("Unbound variable ff", Some(Span::new(src, 13, 15)));
+
+        assert_eq!(d.span.unwrap().line, 2);
+        assert_eq!(d.span.unwrap().col, 8);
+        assert_eq!(d.render(src),
+                   "Unbound variable ff\n  --> 2:8\n  |\n2 |     #T ff)\n  |        ^^");
+        assert_eq!(d.to_string(), "Unbound variable ff at 2:8");
+
+        let first = Span::new(src, 1, 3);
+        for &(lo, hi) in &[(4, 5), (5, 6), (10, 12), (13, 15)] {
+            assert_eq!(Span::after(first, src, lo, hi), Span::new(src, lo, hi));
+        }
+    }
+
+    #[test]
+    fn test_check_closed() {
+        let src = "((/lam x: #B. (if x y x)) #T)";
+        let (t, spans) = lispy::get_code_spanned(src).unwrap();
+
+        let d = check_closed(&t, &spans).unwrap_err();
+        assert_eq!(d.message, "Unbound variable y");
+        assert_eq!(&src[d.span.unwrap().lo..d.span.unwrap().hi], "y");
+
+        let (t, spans) = lispy::get_code_spanned("((/lam x: #B. x) #T)").unwrap();
+        assert!(check_closed(&t, &spans).is_ok());
+
+        let src = "(/lam x: #B. (/lam y: #B, y: #B. x))";
+        let (t, spans) = lispy::get_code_spanned(src).unwrap();
+        let d = check_closed(&t, &spans).unwrap_err();
+        assert_eq!(d.message, "Parameter y is named twice");
+        assert_eq!(&src[d.span.unwrap().lo..d.span.unwrap().hi], "(/lam y: #B, y: #B. x)");
+    }
+}
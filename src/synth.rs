@@ -0,0 +1,207 @@
+//! Searching for closed terms of a given type.
+//!
+//! Read as propositions, types are implications between `#B` and `#Int`, and
+//! a term of a type is a proof of it. The search only builds terms in normal,
+//! eta-long form: a term of an arrow type is a lambda, and a term of a base
+//! type is a constant or a variable (or `!`) applied to enough arguments.
+//! `depth` bounds how deeply arguments nest, which keeps the search finite.
+
+use core::{Term, Ty, TyLit};
+use opt;
+use typecheck::TypeEnv;
+
+/// Up to `limit` distinct closed terms of type `ty` with arguments nested at
+/// most `depth` deep, smallest first.
+pub fn inhabitants(ty: &Ty, depth: usize, limit: usize) -> Vec<Term> {
+    inhabitants_in(&TypeEnv::new(), ty, depth, limit)
+}
+
+/// Like `inhabitants`, for terms whose free variables are typed by `gamma`.
+pub fn inhabitants_in(gamma: &TypeEnv, ty: &Ty, depth: usize, limit: usize) -> Vec<Term> {
+    let mut found = shallow(ty, &mut gamma.clone(), depth, limit);
+
+    // Stable, so terms of the same size keep the order they were found in.
+    found.sort_by_key(opt::size);
+    found
+}
+
+/// A closed term of type `ty` from the shallowest depth that has one.
+pub fn synthesize(ty: &Ty, depth: usize) -> Option<Term> {
+    synthesize_in(&TypeEnv::new(), ty, depth)
+}
+
+/// Like `synthesize`, for a term whose free variables are typed by `gamma`.
+pub fn synthesize_in(gamma: &TypeEnv, ty: &Ty, depth: usize) -> Option<Term> {
+    inhabitants_in(gamma, ty, depth, 1).pop()
+}
+
+/// Up to `limit` distinct terms of type `ty`, by iterative deepening: the
+/// terms of depth 0 come first, then the new ones of depth 1, and so on.
+/// Every step of the search builds at most `limit` terms, so the cost does
+/// not grow with the (exponential) number of inhabitants.
+fn shallow(ty: &Ty, gamma: &mut TypeEnv, depth: usize, limit: usize) -> Vec<Term> {
+    let mut found: Vec<Term> = Vec::new();
+
+    for d in 0..depth + 1 {
+        for t in search(ty, gamma, d, limit) {
+            if found.len() == limit {
+                return found;
+            }
+            if !found.contains(&t) {
+                found.push(t);
+            }
+        }
+    }
+    found
+}
+
+fn search(ty: &Ty, gamma: &mut TypeEnv, depth: usize, limit: usize) -> Vec<Term> {
+    match *ty {
+        Ty::Arrow(ref params, ref res) => {
+            gamma.extend(params.iter().cloned());
+            let bodies = search(res, gamma, depth, limit);
+            let len = gamma.len() - params.len();
+            gamma.truncate(len);
+
+            bodies.into_iter().map(|body| Term::abs(params, body)).collect()
+        }
+        Ty::Bottom => Vec::new(),
+        Ty::Base(lit) => {
+            // Variables, outermost first, then `!`.
+            let mut heads: Vec<(Term, Ty)> = gamma.iter()
+                .enumerate()
+                .map(|(i, hty)| (Term::Var((gamma.len() - i) as u32), hty.clone()))
+                .collect();
+            heads.push((Term::Not, Ty::arrow(&[TyLit::Bool.into()], TyLit::Bool.into())));
+
+            let mut found = Vec::new();
+            for (head, hty) in heads {
+                let left = limit - found.len();
+                found.extend(spines(head, &hty, ty, gamma, depth, left));
+            }
+
+            match lit {
+                TyLit::Bool => found.extend(vec![Term::True, Term::False]),
+                TyLit::Int => found.push(Term::Int(0)),
+            }
+            found.truncate(limit);
+            found
+        }
+    }
+}
+
+/// Up to `limit` terms made of `head` of type `hty`, applied to as many
+/// rounds of arguments as it takes to get a term of type `ty`.
+fn spines(head: Term,
+          hty: &Ty,
+          ty: &Ty,
+          gamma: &mut TypeEnv,
+          depth: usize,
+          limit: usize)
+          -> Vec<Term> {
+    if hty == ty {
+        return if limit > 0 { vec![head] } else { Vec::new() };
+    }
+
+    let (params, res) = match *hty {
+        Ty::Arrow(ref params, ref res) if depth > 0 => (params, res),
+        _ => return Vec::new(),
+    };
+
+    let mut found = Vec::new();
+    for args in arguments(params, gamma, depth - 1, limit) {
+        // `!` applied to a constant is a redex, not a normal form.
+        if head == Term::Not && (args[0] == Term::True || args[0] == Term::False) {
+            continue;
+        }
+        let app = Term::App(Box::new(head.clone()), args);
+        let left = limit - found.len();
+        found.extend(spines(app, res, ty, gamma, depth, left));
+    }
+    found
+}
+
+/// Up to `limit` choices of one term of each of `params`.
+fn arguments(params: &[Ty], gamma: &mut TypeEnv, depth: usize, limit: usize) -> Vec<Vec<Term>> {
+    let mut choices = vec![Vec::new()];
+
+    for param in params {
+        let terms = shallow(param, gamma, depth, limit);
+        choices = choices.iter()
+            .flat_map(|args| {
+                terms.iter().map(move |t| {
+                    let mut args = args.clone();
+                    args.push(t.clone());
+                    args
+                })
+            })
+            .take(limit)
+            .collect();
+    }
+    choices
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use debrujin::name_term;
+    use lispy;
+    use typecheck::TypeCheckVisitor;
+
+    fn ty(s: &str) -> Ty {
+        lispy::get_type(s.as_bytes()).unwrap()
+    }
+
+    fn pretty(t: &Term) -> String {
+        name_term(t).unwrap().pretty()
+    }
+
+    #[test]
+    fn test_synthesize() {
+        let t = synthesize(&ty("(#Int -> (#B -> #Int))"), 3).unwrap();
+        assert_eq!(pretty(&t), "(/lam x: #Int. (/lam y: #B. x))");
+
+        let t = synthesize(&ty("((#Int -> #B), #Int -> #B)"), 3).unwrap();
+        assert_eq!(pretty(&t), "(/lam x: (#Int -> #B), y: #Int. #T)");
+
+        let t = synthesize(&ty("((#B -> #Int) -> #Int)"), 0).unwrap();
+        assert_eq!(pretty(&t), "(/lam x: (#B -> #Int). 0)");
+    }
+
+    #[test]
+    fn test_inhabitants() {
+        let a = ty("((#B -> #Int), (#Int -> #B) -> (#B -> #B))");
+        let found = inhabitants(&a, 2, 1000);
+
+        let mut tc = TypeCheckVisitor::new();
+        for t in &found {
+            assert_eq!(tc.type_of(t).unwrap(), a, "{}", t.unparse());
+        }
+        for (i, t) in found.iter().enumerate() {
+            assert!(!found[..i].contains(t), "{} found twice", t.unparse());
+        }
+        assert!(found.iter().any(|t| pretty(t) == "(/lam x: (#B -> #Int), y: (#Int -> #B). \
+                                                   (/lam z: #B. (y (x z))))"));
+        assert!(!found.iter().any(|t| pretty(t).contains("(! #T)")));
+
+        assert_eq!(inhabitants(&a, 2, 5).len(), 5);
+        assert!(inhabitants(&a, 0, 100).len() < found.len());
+        assert_eq!(inhabitants(&Ty::Bottom, 3, 10), vec![]);
+        assert_eq!(inhabitants(&ty("(#B -> #B)"), 1, 10).len(), 4);
+    }
+
+    #[test]
+    fn test_wide() {
+        // Building every inhabitant of this would take about 10^20 terms.
+        let a = ty("((#Int, #Int, #Int, #Int -> #Int), #Int -> #Int)");
+        let found = inhabitants(&a, 3, 10);
+        assert_eq!(found.len(), 10);
+
+        let mut tc = TypeCheckVisitor::new();
+        for t in &found {
+            assert_eq!(tc.type_of(t).unwrap(), a, "{}", t.unparse());
+        }
+        assert_eq!(pretty(&found[0]), "(/lam x: (#Int, #Int, #Int, #Int -> #Int), y: #Int. y)");
+        assert!(synthesize(&a, 3).is_some());
+    }
+}
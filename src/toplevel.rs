@@ -11,6 +11,10 @@ pub enum Term {
     Not,
     If(Box<Term>, Box<Term>, Box<Term>),
     Bottom,
+    /// A typed hole `?name`, standing for a term still to be written.
+    Hole(String),
+    /// Source that failed to parse, left in place by the error-recovering parser.
+    Error,
 }
 
 impl Term {
@@ -52,6 +56,35 @@ impl Term {
                 format!("(if {} {} {})", cond.unparse(), b1.unparse(), b2.unparse())
             }
             &Term::Bottom => "_|_".into(),
+            &Term::Hole(ref x) => format!("?{}", x),
+            &Term::Error => "<error>".into(),
+        }
+    }
+
+    /// Prints the term in the syntax the parser reads.
+    pub fn pretty(&self) -> String {
+        match *self {
+            Term::Var(ref x) => x.clone(),
+            Term::Int(n) => n.to_string(),
+            Term::True => "#T".into(),
+            Term::False => "#F".into(),
+            Term::Not => "!".into(),
+            Term::Bottom => "_|_".into(),
+            Term::Hole(ref x) => format!("?{}", x),
+            Term::Error => "<error>".into(),
+            Term::Abs(ref args, ref body) => {
+                let args: Vec<String> = args.iter()
+                    .map(|&(ref x, ref ty)| format!("{}: {}", x, ty.unparse()))
+                    .collect();
+                format!("(/lam {}. {})", args.join(", "), body.pretty())
+            }
+            Term::App(ref f, ref args) => {
+                let args: Vec<String> = args.iter().map(|a| a.pretty()).collect();
+                format!("({} {})", f.pretty(), args.join(" "))
+            }
+            Term::If(ref c, ref b1, ref b2) => {
+                format!("(if {} {} {})", c.pretty(), b1.pretty(), b2.pretty())
+            }
         }
     }
 
@@ -80,4 +113,91 @@ impl Term {
     pub fn var<S: Into<String>>(s: S) -> Term {
         Term::Var(s.into())
     }
+
+    /// Equality up to renaming of bound variables. Free variables must match by name.
+    pub fn alpha_eq(&self, other: &Term) -> bool {
+        fn bound(scope: &[&str], x: &str) -> Option<usize> {
+            scope.iter().rev().position(|&y| y == x)
+        }
+
+        fn go<'a>(t1: &'a Term, t2: &'a Term, s1: &mut Vec<&'a str>, s2: &mut Vec<&'a str>) -> bool {
+            match (t1, t2) {
+                (&Term::Var(ref x), &Term::Var(ref y)) => {
+                    match (bound(s1, x), bound(s2, y)) {
+                        (Some(i), Some(j)) => i == j,
+                        (None, None) => x == y,
+                        _ => false,
+                    }
+                }
+                (&Term::Abs(ref a1, ref b1), &Term::Abs(ref a2, ref b2)) => {
+                    if a1.len() != a2.len() ||
+                       a1.iter().zip(a2.iter()).any(|(&(_, ref ty1), &(_, ref ty2))| ty1 != ty2) {
+                        return false;
+                    }
+
+                    s1.extend(a1.iter().map(|&(ref x, _)| x.as_str()));
+                    s2.extend(a2.iter().map(|&(ref x, _)| x.as_str()));
+                    let ret = go(b1, b2, s1, s2);
+                    let (l1, l2) = (s1.len() - a1.len(), s2.len() - a2.len());
+                    s1.truncate(l1);
+                    s2.truncate(l2);
+                    ret
+                }
+                (&Term::App(ref f1, ref x1), &Term::App(ref f2, ref x2)) => {
+                    x1.len() == x2.len() && go(f1, f2, s1, s2) &&
+                    x1.iter().zip(x2.iter()).all(|(a, b)| go(a, b, s1, s2))
+                }
+                (&Term::If(ref c1, ref t1, ref e1), &Term::If(ref c2, ref t2, ref e2)) => {
+                    go(c1, c2, s1, s2) && go(t1, t2, s1, s2) && go(e1, e2, s1, s2)
+                }
+                (t1, t2) => t1 == t2,
+            }
+        }
+
+        go(self, other, &mut Vec::new(), &mut Vec::new())
+    }
+
+    /// Names bound around the subterm at `path` (numbered as in
+    /// `eval::redex`), innermost last.
+    pub fn binders_at(&self, path: &[usize]) -> Option<Vec<&str>> {
+        let mut names = Vec::new();
+        let mut t = self;
+
+        for &i in path {
+            t = match *t {
+                Term::Abs(ref args, ref body) if i == 0 => {
+                    names.extend(args.iter().map(|&(ref x, _)| x.as_str()));
+                    body
+                }
+                Term::App(ref f, _) if i == 0 => f,
+                Term::App(_, ref args) => args.get(i - 1)?,
+                Term::If(ref c, _, _) if i == 0 => c,
+                Term::If(_, ref b1, _) if i == 1 => b1,
+                Term::If(_, _, ref b2) if i == 2 => b2,
+                _ => return None,
+            };
+        }
+
+        Some(names)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lispy;
+
+    #[test]
+    fn test_alpha_eq() {
+        let get = |s: &str| lispy::get_code(s.as_bytes()).unwrap();
+
+        assert!(get("(/lam x: #B. x)").alpha_eq(&get("(/lam y: #B. y)")));
+        assert!(get("(/lam x: #B, y: #B. (x y))").alpha_eq(&get("(/lam a: #B, b: #B. (a b))")));
+        assert!(get("(/lam x: #B. (/lam x: #B. x))").alpha_eq(&get("(/lam y: #B. (/lam z: #B. z))")));
+        assert!(get("(f x)").alpha_eq(&get("(f x)")));
+
+        assert!(!get("(/lam x: #B, y: #B. x)").alpha_eq(&get("(/lam x: #B, y: #B. y)")));
+        assert!(!get("(/lam x: #B. x)").alpha_eq(&get("(/lam x: #Int. x)")));
+        assert!(!get("(/lam x: #B. y)").alpha_eq(&get("(/lam y: #B. y)")));
+        assert!(!get("(f x)").alpha_eq(&get("(f y)")));
+    }
 }
@@ -0,0 +1,235 @@
+//! Recording reduction sequences and exporting them as JSON lines or HTML.
+//!
+//! The JSON lines format has one object per step,
+//! `{"step":0,"term":"...","redex":[1,0],"rule":"not"}`, with the redex given as
+//! a path in the numbering of `eval::redex`, followed by one object describing
+//! how evaluation ended. Terms are printed with `Term::unparse`, so two traces
+//! can be compared with a plain line diff.
+
+use std::fmt::Write;
+
+use core::Term;
+use debugger::unparse_marked;
+use eval::{self, Rule, Step, StuckReason};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TraceStep {
+    /// The term before the step.
+    pub term: Term,
+    pub redex: Vec<usize>,
+    pub rule: Rule,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum End {
+    Value(Term),
+    Stuck {
+        term: Term,
+        redex: Vec<usize>,
+        reason: StuckReason,
+    },
+    OutOfFuel(Term),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Trace {
+    pub steps: Vec<TraceStep>,
+    pub end: End,
+}
+
+/// Evaluates `t`, recording every step. Stops after `fuel` steps if given.
+pub fn record(t: &Term, fuel: Option<u64>) -> Trace {
+    let mut steps = Vec::new();
+    let mut term = t.clone();
+
+    let end = loop {
+        if fuel.map_or(false, |fuel| steps.len() as u64 >= fuel) && !term.is_val() {
+            break End::OutOfFuel(term);
+        }
+
+        match eval::step(&term) {
+            Step::Value => break End::Value(term),
+            Step::Stuck(_, reason) => {
+                break End::Stuck {
+                          redex: eval::redex(&term).unwrap_or_default(),
+                          term: term,
+                          reason: reason,
+                      }
+            }
+            Step::Reduced(next, rule) => {
+                steps.push(TraceStep {
+                               redex: eval::redex(&term).unwrap_or_default(),
+                               term: term,
+                               rule: rule,
+                           });
+                term = next;
+            }
+        }
+    };
+
+    Trace {
+        steps: steps,
+        end: end,
+    }
+}
+
+fn json_str(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_path(path: &[usize]) -> String {
+    let path: Vec<String> = path.iter().map(|i| i.to_string()).collect();
+    format!("[{}]", path.join(","))
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// `t` as HTML with the subterm at `path` wrapped in `<mark>`.
+fn html_marked(t: &Term, path: &[usize]) -> String {
+    // Mark with control characters first so the markup is not escaped.
+    let marked = unparse_marked(t, path, &|t| format!("\u{1}{}\u{2}", t.unparse()));
+    html_escape(&marked).replace('\u{1}', "<mark>").replace('\u{2}', "</mark>")
+}
+
+impl Trace {
+    pub fn to_json_lines(&self) -> String {
+        let mut out = String::new();
+
+        for (i, s) in self.steps.iter().enumerate() {
+            writeln!(out,
+                     "{{\"step\":{},\"term\":{},\"redex\":{},\"rule\":{}}}",
+                     i,
+                     json_str(&s.term.unparse()),
+                     json_path(&s.redex),
+                     json_str(&s.rule.to_string()))
+                .unwrap();
+        }
+
+        let n = self.steps.len();
+        let end = match self.end {
+            End::Value(ref t) => format!("\"term\":{},\"end\":\"value\"", json_str(&t.unparse())),
+            End::Stuck { ref term, ref redex, ref reason } => {
+                format!("\"term\":{},\"redex\":{},\"end\":\"stuck\",\"reason\":{}",
+                        json_str(&term.unparse()),
+                        json_path(redex),
+                        json_str(&reason.to_string()))
+            }
+            End::OutOfFuel(ref t) => {
+                format!("\"term\":{},\"end\":\"out of fuel\"", json_str(&t.unparse()))
+            }
+        };
+        writeln!(out, "{{\"step\":{},{}}}", n, end).unwrap();
+
+        out
+    }
+
+    /// A standalone page with one table row per step, redexes highlighted.
+    pub fn to_html(&self) -> String {
+        let mut out = String::from(r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Reduction trace</title>
+<style>
+body { font-family: sans-serif; }
+td { padding: 0.2em 1em; vertical-align: top; }
+td.term { font-family: monospace; }
+mark { background: #fd6; }
+tr.end td { font-weight: bold; }
+</style>
+</head>
+<body>
+<table>
+<tr><th>Step</th><th>Term</th><th>Rule</th></tr>
+"#);
+
+        for (i, s) in self.steps.iter().enumerate() {
+            writeln!(out,
+                     "<tr><td>{}</td><td class=\"term\">{}</td><td>{}</td></tr>",
+                     i,
+                     html_marked(&s.term, &s.redex),
+                     s.rule)
+                .unwrap();
+        }
+
+        let n = self.steps.len();
+        let (term, what) = match self.end {
+            End::Value(ref t) => (html_escape(&t.unparse()), "value".into()),
+            End::Stuck { ref term, ref redex, ref reason } => {
+                (html_marked(term, redex), format!("stuck: {}", html_escape(&reason.to_string())))
+            }
+            End::OutOfFuel(ref t) => (html_escape(&t.unparse()), "out of fuel".into()),
+        };
+        writeln!(out,
+                 "<tr class=\"end\"><td>{}</td><td class=\"term\">{}</td><td>{}</td></tr>",
+                 n,
+                 term,
+                 what)
+            .unwrap();
+
+        out.push_str("</table>\n</body>\n</html>\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use corpus::{get, PROGRAMS};
+
+    #[test]
+    fn test_record() {
+        for code in PROGRAMS {
+            let trace = record(&get(code), None);
+            match trace.end {
+                End::Value(_) => {}
+                ref end => panic!("{}: {:?}", code, end),
+            }
+        }
+
+        let trace = record(&get("((/lam x: #B. x) (if 1 #T #F))"), None);
+        assert_eq!(trace.steps.len(), 0);
+        assert_eq!(trace.end,
+                   End::Stuck {
+                       term: get("((/lam x: #B. x) (if 1 #T #F))"),
+                       redex: vec![1],
+                       reason: StuckReason::NonBoolCondition,
+                   });
+
+        let trace = record(&get("(! (! #T))"), Some(1));
+        assert_eq!(trace.end, End::OutOfFuel(get("(! #F)")));
+    }
+
+    #[test]
+    fn test_export() {
+        let trace = record(&get("((/lam x: #B. (if x 1 2)) (! #F))"), None);
+
+        assert_eq!(trace.to_json_lines(),
+                   r#"{"step":0,"term":"((/lam : #B. (If 1 1 2)) (! #F))","redex":[1],"rule":"not"}
+{"step":1,"term":"((/lam : #B. (If 1 1 2)) #T)","redex":[],"rule":"beta"}
+{"step":2,"term":"(If #T 1 2)","redex":[],"rule":"if-true"}
+{"step":3,"term":"1","end":"value"}
+"#);
+
+        let html = trace.to_html();
+        assert!(html.contains("<td class=\"term\">((/lam : #B. (If 1 1 2)) <mark>(! #F)</mark>)</td>"));
+        assert!(html.contains("<td>3</td><td class=\"term\">1</td><td>value</td>"));
+
+        let trace = record(&get("((/lam f: (#B -> #B). (f #F)) !)"), None);
+        assert!(trace.to_html().contains("(#B -&gt; #B)"));
+        assert_eq!(json_str("a\"b\\c\n"), r#""a\"b\\c\n""#);
+    }
+}
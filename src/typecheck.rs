@@ -1,150 +1,499 @@
+use std::fmt;
+
 use core::{Term, Ty, TyLit};
-use core::supervisitor::{self as sv, SuperVisitor, TermFold};
+use derivation::{Derivation, TypingRule};
 use errors::*;
 
+/// Types of the enclosing binders, innermost last, so `Var(k)` has type
+/// `gamma[gamma.len() - k]`.
 pub type TypeEnv = Vec<Ty>;
 
-pub struct TypeCheckVisitor {
-    gamma: TypeEnv,
+/// Why a term is ill-typed. Every variant carries the offending subterm and
+/// the context it was checked in.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TypeError {
+    UnknownVariable { var: u32, context: TypeEnv },
+    /// A function was applied to the wrong number of arguments.
+    ArityMismatch {
+        expected: usize,
+        found: usize,
+        term: Term,
+        context: TypeEnv,
+    },
+    /// The argument at `position` (counting from 1) has the wrong type.
+    ArgumentMismatch {
+        position: usize,
+        expected: Ty,
+        found: Ty,
+        term: Term,
+        context: TypeEnv,
+    },
+    /// Something that is not a function was applied.
+    NonFunction {
+        found: Ty,
+        term: Term,
+        context: TypeEnv,
+    },
+    NonBoolCondition {
+        found: Ty,
+        term: Term,
+        context: TypeEnv,
+    },
+    BranchMismatch {
+        then: Ty,
+        else_: Ty,
+        term: Term,
+        context: TypeEnv,
+    },
+    /// The type of the term depends on a hole whose type is not determined.
+    Undetermined { term: Term, context: TypeEnv },
 }
 
-pub type Judgement = Result<Ty>;
-
-impl TermFold for Judgement {
-    fn empty<'a, SV>(_: &mut SV) -> Judgement
-        where SV: SuperVisitor<'a, Output = Self>
-    {
-        Ok(Ty::Bottom)
+impl TypeError {
+    /// The ill-typed subterm.
+    pub fn term(&self) -> Term {
+        match *self {
+            TypeError::UnknownVariable { var, .. } => Term::Var(var),
+            TypeError::ArityMismatch { ref term, .. } |
+            TypeError::ArgumentMismatch { ref term, .. } |
+            TypeError::NonFunction { ref term, .. } |
+            TypeError::NonBoolCondition { ref term, .. } |
+            TypeError::BranchMismatch { ref term, .. } |
+            TypeError::Undetermined { ref term, .. } => term.clone(),
+        }
     }
 
-    fn fold_if<'a, SV>(_: &mut SV, cond: Judgement, then: Judgement, else_: Judgement) -> Judgement
-        where SV: SuperVisitor<'a, Output = Self>
-    {
-        let (c, t, e) = (cond?, then?, else_?);
-
-        match (c == Ty::Base(TyLit::Bool), t == e) {
-            (true, true) => Ok(t),
-            (false, true) => Err(type_err("If condition is not boolean")),
-            (true, false) => Err(type_err("If condition branches differ in type")),
-            (false, false) => Err(type_err("simply terrible")),
+    pub fn context(&self) -> &[Ty] {
+        match *self {
+            TypeError::UnknownVariable { ref context, .. } |
+            TypeError::ArityMismatch { ref context, .. } |
+            TypeError::ArgumentMismatch { ref context, .. } |
+            TypeError::NonFunction { ref context, .. } |
+            TypeError::NonBoolCondition { ref context, .. } |
+            TypeError::BranchMismatch { ref context, .. } |
+            TypeError::Undetermined { ref context, .. } => context,
         }
     }
+}
 
-    fn fold_app<'a, SV>(_: &mut SV, f: Judgement, js: &[Judgement]) -> Judgement
-        where SV: SuperVisitor<'a, Output = Self>
-    {
-        let f = f?;
-
-        match f {
-            Ty::Arrow(arg, res) => {
-                let mut test = true;
-                for (x, y) in arg.iter().zip(js.iter()) {
-                    test &= if let &Ok(ref j) = y { x == j } else { false };
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TypeError::UnknownVariable { var, .. } => write!(f, "Unknown variable {}", var)?,
+            TypeError::ArityMismatch { expected, found, ref term, .. } => {
+                write!(f,
+                       "{} expects {} argument(s) but was given {}",
+                       term.unparse(),
+                       expected,
+                       found)?
+            }
+            TypeError::ArgumentMismatch { position, ref expected, ref found, ref term, .. } => {
+                write!(f,
+                       "Argument {} of {} has type {} but {} was expected",
+                       position,
+                       term.unparse(),
+                       found,
+                       expected)?
+            }
+            TypeError::NonFunction { ref found, ref term, .. } => {
+                write!(f,
+                       "{} applies a value of non-function type {}",
+                       term.unparse(),
+                       found)?
+            }
+            TypeError::NonBoolCondition { ref found, ref term, .. } => {
+                write!(f,
+                       "The condition of {} has type {} instead of #B",
+                       term.unparse(),
+                       found)?
+            }
+            TypeError::BranchMismatch { ref then, ref else_, ref term, .. } => {
+                write!(f,
+                       "The branches of {} have different types {} and {}",
+                       term.unparse(),
+                       then,
+                       else_)?
+            }
+            TypeError::Undetermined { ref term, .. } => {
+                write!(f,
+                       "The type of {} depends on a hole whose type is not determined",
+                       term.unparse())?
+            }
+        }
 
-                }
+        if !self.context().is_empty() {
+            write!(f, " (context {})", show_context(self.context()))?;
+        }
 
-                test &= js.len() == arg.len();
+        Ok(())
+    }
+}
 
-                println!("js={:?}", js);
-                println!("arg={:?}", arg);
+/// A context as `n: T_n, ..., 1: T_1`, each type after the index that refers to it.
+pub fn show_context(context: &[Ty]) -> String {
+    let vars: Vec<String> = context.iter()
+        .enumerate()
+        .map(|(i, ty)| format!("{}: {}", context.len() - i, ty))
+        .collect();
+    vars.join(", ")
+}
 
-                if test {
-                    Ok(res.as_ref().clone())
-                } else {
-                    Err(type_err("Type mismatch in function application"))
-                }
+/// A hole met while typechecking. `expected` is the type the surrounding term
+/// requires of it, if that is determined.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Hole {
+    pub name: String,
+    pub expected: Option<Ty>,
+    pub context: TypeEnv,
+    /// Path of the hole, numbered as in `eval::redex`.
+    pub path: Vec<usize>,
+}
 
+impl Hole {
+    /// The hole and its expected type, then one line per variable in scope,
+    /// named by `names` (the binders around the hole, innermost last).
+    /// Shadowed variables are left out.
+    pub fn report(&self, names: &[&str]) -> String {
+        let expected = self.expected.as_ref().map_or("?".into(), |ty| ty.to_string());
+        let mut out = format!("?{} : {}", self.name, expected);
+
+        let mut seen = Vec::new();
+        let mut vars = Vec::new();
+        for (k, ty) in self.context.iter().rev().enumerate() {
+            let name = names.len().checked_sub(k + 1).map(|i| names[i]);
+            if name.map_or(false, |x| seen.contains(&x)) {
+                continue;
             }
-            _ => Err(type_err("Non-function in function application position")),
+            seen.extend(name);
+            vars.push(match name {
+                Some(x) => format!("\n  {} : {}", x, ty),
+                None => format!("\n  {} : {}", k + 1, ty),
+            });
+        }
+        for v in vars.iter().rev() {
+            out.push_str(v);
         }
-    }
 
-    fn fold_abs<'a, SV>(_: &mut SV, ty: &[Judgement], body: Judgement) -> Judgement
-        where SV: SuperVisitor<'a, Output = Self>
-    {
+        out
+    }
+}
 
+impl fmt::Display for Hole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.expected {
+            Some(ref ty) => write!(f, "?{} : {}", self.name, ty)?,
+            None => write!(f, "?{} : ?", self.name)?,
+        }
+        if !self.context.is_empty() {
+            write!(f, " (context {})", show_context(&self.context))?;
+        }
+        Ok(())
+    }
+}
 
-        let huh = ty.iter()
-            .fold(Ok(Vec::new()), |acc, elem| if let Ok(mut vec) = acc {
-                if elem.is_ok() {
-                    let j = elem.as_ref().unwrap().clone();
-                    vec.push(j);
-                    Ok(vec)
-                } else {
-                    Err(type_err("error among abs whatever"))
-                }
-            } else {
-                acc
-            });
+/// Whether `found` is `expected`. A type that is not known because it depends
+/// on a hole agrees with every type.
+pub fn agrees(expected: &Ty, found: &Option<Ty>) -> bool {
+    found.as_ref().map_or(true, |found| found == expected)
+}
 
-        let ty = huh?;
-        let by = body?;
-        Ok(Ty::arrow(&ty, by))
+impl From<TypeError> for Error {
+    fn from(e: TypeError) -> Error {
+        type_err(&e.to_string())
     }
 }
 
+pub struct TypeCheckVisitor {
+    /// Types of the variables free in the terms checked.
+    context: TypeEnv,
+    gamma: TypeEnv,
+    /// Path of the term being checked, numbered as in `eval::redex`.
+    path: Vec<usize>,
+    /// Path of the subterm the last error points at.
+    error_path: Vec<usize>,
+    /// Derivations of the judgements made so far, when recording.
+    derivations: Option<Vec<Derivation>>,
+    /// Holes of the last term checked, in the order they were met.
+    holes: Vec<Hole>,
+}
+
+pub type Judgement = ::std::result::Result<Ty, TypeError>;
+
+/// A type, or `None` when it depends on a hole whose type is not determined.
+pub type Partial = ::std::result::Result<Option<Ty>, TypeError>;
+
 impl TypeCheckVisitor {
     pub fn new() -> TypeCheckVisitor {
-        TypeCheckVisitor { gamma: TypeEnv::new() }
+        TypeCheckVisitor::with_context(TypeEnv::new())
+    }
+
+    /// A checker for terms whose free variables are typed by `context`, as
+    /// if they were under binders of those types.
+    pub fn with_context(context: TypeEnv) -> TypeCheckVisitor {
+        TypeCheckVisitor {
+            gamma: context.clone(),
+            context: context,
+            path: Vec::new(),
+            error_path: Vec::new(),
+            derivations: None,
+            holes: Vec::new(),
+        }
     }
 
+    /// The type of `t`. Holes take the type their context requires, see
+    /// `holes`. A type that depends on a hole whose type is not determined is
+    /// an error; `partial_type_of` allows it.
     pub fn type_of(&mut self, t: &Term) -> Judgement {
-        let ret = sv::walk_term(self, t);
+        match self.partial_type_of(t)? {
+            Some(ty) => Ok(ty),
+            None => {
+                let e = TypeError::Undetermined {
+                    term: t.clone(),
+                    context: self.context.clone(),
+                };
+                self.fail(e, None)
+            }
+        }
+    }
+
+    /// Like `type_of`, with `None` for a type that depends on a hole whose
+    /// type is not determined.
+    pub fn partial_type_of(&mut self, t: &Term) -> Partial {
+        self.holes.clear();
+        let ret = self.check(t, None);
         self.reset();
         ret
     }
 
-    fn reset(&mut self) {
-        self.gamma.clear();
+    /// Like `partial_type_of`, also returning the path of the subterm to
+    /// blame: the argument or condition of the wrong type, or the whole
+    /// offending term.
+    pub fn type_of_located(&mut self,
+                           t: &Term)
+                           -> ::std::result::Result<Option<Ty>, (TypeError, Vec<usize>)> {
+        self.partial_type_of(t).map_err(|e| (e, self.error_path.clone()))
     }
-}
 
-impl<'a> SuperVisitor<'a> for TypeCheckVisitor {
-    type Output = Judgement;
+    /// Like `partial_type_of`, also returning the full typing derivation.
+    pub fn derive(&mut self, t: &Term) -> ::std::result::Result<Derivation, TypeError> {
+        self.derivations = Some(Vec::new());
+        let ret = self.partial_type_of(t);
+        let derivation = self.derivations.take().and_then(|mut ds| ds.pop());
 
-    fn visit_true(&mut self) -> Judgement {
-        Ok(TyLit::Bool.into())
+        ret.map(|_| derivation.unwrap())
     }
 
-    fn visit_false(&mut self) -> Judgement {
-        Ok(TyLit::Bool.into())
+    /// The holes of the term last passed to `type_of`, even if it was
+    /// ill-typed, up to the error.
+    pub fn holes(&self) -> &[Hole] {
+        &self.holes
     }
 
-    fn visit_not(&mut self) -> Judgement {
-        Ok(Ty::arrow(&[TyLit::Bool.into()], TyLit::Bool.into()))
+    fn reset(&mut self) {
+        self.gamma.clone_from(&self.context);
+        self.path.clear();
     }
 
-    fn visit_stuck(&mut self) -> Judgement {
-        Ok(Ty::Bottom)
+    /// Fails with `e`, blaming the child `child` of the current term if given.
+    fn fail<T>(&mut self, e: TypeError, child: Option<usize>) -> ::std::result::Result<T, TypeError> {
+        self.error_path = self.path.clone();
+        self.error_path.extend(child);
+        Err(e)
     }
 
-    fn visit_int(&mut self, _: i64) -> Judgement {
-        Ok(TyLit::Int.into())
+    fn check_child(&mut self, i: usize, t: &Term, expected: Option<&Ty>) -> Partial {
+        self.path.push(i);
+        let ret = self.check(t, expected);
+        self.path.pop();
+        ret
     }
 
-    fn visit_ty(&mut self, ty: &'a Ty) -> Judgement {
-        Ok(ty.clone())
+    /// Checks `t` in a position requiring `expected`. The requirement is only
+    /// used to type holes; any mismatch is found by the enclosing term.
+    fn check(&mut self, t: &Term, expected: Option<&Ty>) -> Partial {
+        let mark = self.derivations.as_ref().map(|ds| ds.len());
+        let ty = self.infer(t, expected)?;
+
+        if let (Some(mark), Some(ds)) = (mark, self.derivations.as_mut()) {
+            let premises = ds.split_off(mark);
+            ds.push(Derivation {
+                        rule: TypingRule::of(t),
+                        context: self.gamma.clone(),
+                        term: t.clone(),
+                        ty: ty.clone(),
+                        premises: premises,
+                    });
+        }
+
+        Ok(ty)
     }
 
-    fn visit_var(&mut self, v: u32) -> Judgement {
-        let idx = self.gamma.len() - v as usize;
-        match self.gamma.get(idx) {
-            Some(ty) => Ok(ty.clone()),
-            None => Err(type_err("Unknown variable")),
+    /// Checks `(?f args...)` with the arguments first, so the hole can be
+    /// expected to have the function type they and `expected` determine.
+    fn check_hole_app(&mut self,
+                      f: &Term,
+                      args: &[Term],
+                      expected: Option<&Ty>)
+                      -> ::std::result::Result<(Option<Ty>, Vec<Option<Ty>>), TypeError> {
+        let mark = self.derivations.as_ref().map(|ds| ds.len());
+
+        let mut found = Vec::new();
+        for (i, a) in args.iter().enumerate() {
+            found.push(self.check_child(i + 1, a, None)?);
         }
+
+        let params: Option<Vec<Ty>> = found.iter().cloned().collect();
+        let fty = match (params, expected) {
+            (Some(params), Some(res)) => Some(Ty::arrow(&params, res.clone())),
+            _ => None,
+        };
+        let fty = self.check_child(0, f, fty.as_ref())?;
+
+        // Keep the premises in path order, the function first.
+        if let (Some(mark), Some(ds)) = (mark, self.derivations.as_mut()) {
+            let fd = ds.pop().unwrap();
+            ds.insert(mark, fd);
+        }
+
+        Ok((fty, found))
     }
 
-    fn visit_abs(&mut self, ty_vars: &'a [Ty], body: &'a Term) -> Judgement {
-        self.gamma.extend(ty_vars.iter().cloned());
-        let ret = sv::walk_abs(self, ty_vars, body);
-        self.gamma.pop();
-        ret
+    fn infer(&mut self, t: &Term, expected: Option<&Ty>) -> Partial {
+        match *t {
+            Term::True | Term::False => Ok(Some(TyLit::Bool.into())),
+            Term::Int(_) => Ok(Some(TyLit::Int.into())),
+            Term::Not => Ok(Some(Ty::arrow(&[TyLit::Bool.into()], TyLit::Bool.into()))),
+            Term::Stuck => Ok(Some(Ty::Bottom)),
+            Term::Hole(ref x) => {
+                self.holes.push(Hole {
+                                    name: x.clone(),
+                                    expected: expected.cloned(),
+                                    context: self.gamma.clone(),
+                                    path: self.path.clone(),
+                                });
+                Ok(expected.cloned())
+            }
+            Term::Var(v) => {
+                let ty = self.gamma
+                    .len()
+                    .checked_sub(v as usize)
+                    .and_then(|idx| self.gamma.get(idx))
+                    .cloned();
+
+                match ty {
+                    Some(ty) => Ok(Some(ty)),
+                    None => {
+                        let e = TypeError::UnknownVariable {
+                            var: v,
+                            context: self.gamma.clone(),
+                        };
+                        self.fail(e, None)
+                    }
+                }
+            }
+            Term::Abs(ref ty_vars, ref body) => {
+                let res = match expected {
+                    Some(&Ty::Arrow(ref params, ref res)) if params == ty_vars => Some(&**res),
+                    _ => None,
+                };
+
+                self.gamma.extend(ty_vars.iter().cloned());
+                let ret = self.check_child(0, body, res);
+                let len = self.gamma.len() - ty_vars.len();
+                self.gamma.truncate(len);
+                Ok(ret?.map(|res| Ty::arrow(ty_vars, res)))
+            }
+            Term::App(ref f, ref args) => {
+                let (fty, found) = if let Term::Hole(_) = **f {
+                    self.check_hole_app(f, args, expected)?
+                } else {
+                    let fty = self.check_child(0, f, None)?;
+                    let params = match fty {
+                        Some(Ty::Arrow(ref params, _)) => params.clone(),
+                        _ => Vec::new(),
+                    };
+
+                    let mut found = Vec::new();
+                    for (i, a) in args.iter().enumerate() {
+                        found.push(self.check_child(i + 1, a, params.get(i))?);
+                    }
+                    (fty, found)
+                };
+
+                let fty = match fty {
+                    Some(fty) => fty,
+                    None => return Ok(expected.cloned()),
+                };
+
+                match fty {
+                    Ty::Arrow(params, res) => {
+                        if params.len() != found.len() {
+                            let e = TypeError::ArityMismatch {
+                                expected: params.len(),
+                                found: found.len(),
+                                term: t.clone(),
+                                context: self.gamma.clone(),
+                            };
+                            return self.fail(e, None);
+                        }
+
+                        for (i, (expected, found)) in params.into_iter().zip(found).enumerate() {
+                            if !agrees(&expected, &found) {
+                                let e = TypeError::ArgumentMismatch {
+                                    position: i + 1,
+                                    expected: expected,
+                                    found: found.unwrap(),
+                                    term: t.clone(),
+                                    context: self.gamma.clone(),
+                                };
+                                return self.fail(e, Some(i + 1));
+                            }
+                        }
+
+                        Ok(Some(*res))
+                    }
+                    fty => {
+                        let e = TypeError::NonFunction {
+                            found: fty,
+                            term: t.clone(),
+                            context: self.gamma.clone(),
+                        };
+                        self.fail(e, Some(0))
+                    }
+                }
+            }
+            Term::If(ref cond, ref b1, ref b2) => {
+                let b = TyLit::Bool.into();
+                let c = self.check_child(0, cond, Some(&b))?;
+                let then = self.check_child(1, b1, expected)?;
+                let else_ = self.check_child(2, b2, then.as_ref().or(expected))?;
+
+                if !agrees(&b, &c) {
+                    let e = TypeError::NonBoolCondition {
+                        found: c.unwrap(),
+                        term: t.clone(),
+                        context: self.gamma.clone(),
+                    };
+                    self.fail(e, Some(0))
+                } else if then.is_some() && !agrees(then.as_ref().unwrap(), &else_) {
+                    let e = TypeError::BranchMismatch {
+                        then: then.unwrap(),
+                        else_: else_.unwrap(),
+                        term: t.clone(),
+                        context: self.gamma.clone(),
+                    };
+                    self.fail(e, None)
+                } else {
+                    Ok(then.or(else_))
+                }
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::TypeCheckVisitor;
+    use super::{Hole, TypeCheckVisitor, TypeError};
     use debrujin::RenameVisitor;
     use core::{Term, Ty, TyLit};
     use lispy;
@@ -184,4 +533,138 @@ mod test {
         println!("{:?}", ast);
         assert_eq!(tc.type_of(&ast).unwrap(), TyLit::Int.into());
     }
+
+    #[test]
+    fn test_errors() {
+        let mut tc = TypeCheckVisitor::new();
+        let b: Ty = TyLit::Bool.into();
+        let i: Ty = TyLit::Int.into();
+
+        assert_eq!(tc.type_of(&get("((/lam x: #B, y: #Int. x) #T #F)")).unwrap_err(),
+                   TypeError::ArgumentMismatch {
+                       position: 2,
+                       expected: i.clone(),
+                       found: b.clone(),
+                       term: get("((/lam x: #B, y: #Int. x) #T #F)"),
+                       context: vec![],
+                   });
+        assert_eq!(tc.type_of(&get("(! #T #F)")).unwrap_err(),
+                   TypeError::ArityMismatch {
+                       expected: 1,
+                       found: 2,
+                       term: get("(! #T #F)"),
+                       context: vec![],
+                   });
+        assert_eq!(tc.type_of(&get("(/lam f: #Int. (f 1))")).unwrap_err(),
+                   TypeError::NonFunction {
+                       found: i.clone(),
+                       term: Term::app(Term::Var(1), &[Term::Int(1)]),
+                       context: vec![i.clone()],
+                   });
+
+        let e = tc.type_of(&get("(/lam x: #B, n: #Int. (if n x x))")).unwrap_err();
+        assert_eq!(e,
+                   TypeError::NonBoolCondition {
+                       found: i.clone(),
+                       term: Term::if_(Term::Var(1), Term::Var(2), Term::Var(2)),
+                       context: vec![b.clone(), i.clone()],
+                   });
+        assert_eq!(e.to_string(),
+                   "The condition of (If 1 2 2) has type #Int instead of #B (context 2: #B, 1: #Int)");
+
+        match tc.type_of(&get("(if #T 1 #F)")).unwrap_err() {
+            TypeError::BranchMismatch { then, else_, .. } => assert_eq!((then, else_), (i, b)),
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_error_path() {
+        let mut tc = TypeCheckVisitor::new();
+
+        let (_, path) = tc.type_of_located(&get("(/lam x: #Int. (if #T (! x) #F))")).unwrap_err();
+        assert_eq!(path, vec![0, 1, 1]);
+        let (_, path) = tc.type_of_located(&get("(if 1 #T #F)")).unwrap_err();
+        assert_eq!(path, vec![0]);
+        let (_, path) = tc.type_of_located(&get("(if #T 1 #F)")).unwrap_err();
+        assert_eq!(path, Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_holes() {
+        let mut tc = TypeCheckVisitor::new();
+        let b: Ty = TyLit::Bool.into();
+        let i: Ty = TyLit::Int.into();
+
+        let t = get("(/lam x: #Int, y: #B. (if ?c (! ?b) #F))");
+        assert_eq!(tc.type_of(&t).unwrap(), Ty::arrow(&[i.clone(), b.clone()], b.clone()));
+        assert_eq!(tc.holes(),
+                   &[Hole {
+                         name: "c".into(),
+                         expected: Some(b.clone()),
+                         context: vec![i.clone(), b.clone()],
+                         path: vec![0, 0],
+                     },
+                     Hole {
+                         name: "b".into(),
+                         expected: Some(b.clone()),
+                         context: vec![i.clone(), b.clone()],
+                         path: vec![0, 1, 1],
+                     }]);
+        assert_eq!(tc.holes()[0].report(&["x", "y"]), "?c : #B\n  x : #Int\n  y : #B");
+
+        // Function holes are expected to take the arguments they are given.
+        tc.type_of(&get("(/lam n: #Int. (if (?f n) 1 2))")).unwrap();
+        assert_eq!(tc.holes()[0].expected, Some(Ty::arrow(&[i.clone()], b.clone())));
+
+        // Branches are checked in order, so only the else branch learns its
+        // type from the other one.
+        assert_eq!(tc.type_of(&get("(if #T 1 ?x)")).unwrap(), i.clone());
+        assert_eq!(tc.holes()[0].expected, Some(i.clone()));
+        assert_eq!(tc.type_of(&get("(if #T ?x 1)")).unwrap(), i.clone());
+        assert_eq!(tc.holes()[0].expected, None);
+
+        // Nothing determines the type of `?h`, so neither is the term's.
+        let t = get("(/lam x: #B. ((/lam y: #B. ?h) #T))");
+        assert_eq!(tc.partial_type_of(&t).unwrap(), None);
+        assert_eq!(tc.holes()[0].report(&["x", "x"]), "?h : ?\n  x : #B");
+        assert_eq!(tc.type_of(&t).unwrap_err(),
+                   TypeError::Undetermined {
+                       term: t.clone(),
+                       context: vec![],
+                   });
+        assert!(tc.type_of(&get("(if ?c 1 #F)")).is_err());
+    }
+
+    #[test]
+    fn test_bottom() {
+        let mut tc = TypeCheckVisitor::new();
+        let b: Ty = TyLit::Bool.into();
+
+        // `_|_` only has type `_|_`; it is not a stand-in for unknown types.
+        let t = Term::app(get("(/lam x: #B. x)"), &[Term::Stuck]);
+        assert_eq!(tc.type_of(&t).unwrap_err(),
+                   TypeError::ArgumentMismatch {
+                       position: 1,
+                       expected: b.clone(),
+                       found: Ty::Bottom,
+                       term: t.clone(),
+                       context: vec![],
+                   });
+        let t = Term::if_(Term::Stuck, Term::Int(1), Term::Int(2));
+        assert_eq!(tc.type_of(&t).unwrap_err(),
+                   TypeError::NonBoolCondition {
+                       found: Ty::Bottom,
+                       term: t.clone(),
+                       context: vec![],
+                   });
+        let t = Term::app(Term::Stuck, &[Term::Int(1), Term::Int(2)]);
+        assert_eq!(tc.type_of(&t).unwrap_err(),
+                   TypeError::NonFunction {
+                       found: Ty::Bottom,
+                       term: t.clone(),
+                       context: vec![],
+                   });
+        assert!(tc.type_of(&Term::if_(Term::True, Term::Stuck, Term::Int(1))).is_err());
+    }
 }
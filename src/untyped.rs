@@ -0,0 +1,170 @@
+//! Untyped lambda calculus, the target of type erasure.
+
+use core::Term as TTerm;
+use errors::*;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Term {
+    Var(u32),
+    /// Number of binders and body.
+    Abs(usize, Box<Term>),
+    App(Box<Term>, Vec<Term>),
+    True,
+    False,
+    Int(i64),
+    Not,
+    If(Box<Term>, Box<Term>, Box<Term>),
+    Stuck,
+}
+
+/// Drops every type annotation from a typed term.
+pub fn erase(t: &TTerm) -> Result<Term> {
+    Ok(match *t {
+        TTerm::Var(k) => Term::Var(k),
+        TTerm::Abs(ref tys, ref body) => Term::Abs(tys.len(), Box::new(erase(body)?)),
+        TTerm::App(ref f, ref args) => {
+            Term::App(Box::new(erase(f)?), args.iter().map(erase).collect::<Result<_>>()?)
+        }
+        TTerm::True => Term::True,
+        TTerm::False => Term::False,
+        TTerm::Int(n) => Term::Int(n),
+        TTerm::Not => Term::Not,
+        TTerm::If(ref c, ref b1, ref b2) => {
+            Term::If(Box::new(erase(c)?), Box::new(erase(b1)?), Box::new(erase(b2)?))
+        }
+        TTerm::Stuck => Term::Stuck,
+        TTerm::Hole(ref x) => bail!("Cannot erase the unfilled hole ?{}", x),
+    })
+}
+
+impl Term {
+    pub fn is_val(&self) -> bool {
+        match *self {
+            Term::True | Term::False | Term::Not | Term::Abs(..) | Term::Var(_) | Term::Int(_) => {
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn unparse(&self) -> String {
+        match *self {
+            Term::True => "#T".into(),
+            Term::False => "#F".into(),
+            Term::Not => "!".into(),
+            Term::Int(n) => n.to_string(),
+            Term::Var(x) => x.to_string(),
+            Term::App(ref f, ref args) => {
+                let args: Vec<String> = args.iter().map(|a| a.unparse()).collect();
+                format!("({} {})", f.unparse(), args.join(" "))
+            }
+            Term::Abs(n, ref b) => {
+                let binders: Vec<&str> = (0..n).map(|_| "_").collect();
+                format!("(/lam {}. {})", binders.join(", "), b.unparse())
+            }
+            Term::If(ref c, ref b1, ref b2) => {
+                format!("(If {} {} {})", c.unparse(), b1.unparse(), b2.unparse())
+            }
+            Term::Stuck => "#STUCK#".into(),
+        }
+    }
+
+    fn shift(&self, d: i64, cutoff: u32) -> Term {
+        match *self {
+            Term::Var(k) if k > cutoff => Term::Var((k as i64 + d) as u32),
+            Term::Abs(n, ref body) => Term::Abs(n, Box::new(body.shift(d, cutoff + n as u32))),
+            Term::App(ref f, ref args) => {
+                Term::App(Box::new(f.shift(d, cutoff)),
+                          args.iter().map(|a| a.shift(d, cutoff)).collect())
+            }
+            Term::If(ref c, ref b1, ref b2) => {
+                Term::If(Box::new(c.shift(d, cutoff)),
+                         Box::new(b1.shift(d, cutoff)),
+                         Box::new(b2.shift(d, cutoff)))
+            }
+            ref t => t.clone(),
+        }
+    }
+
+    /// Substitutes `args` for the innermost `args.len()` binders.
+    fn subst(&self, args: &[Term], depth: u32) -> Term {
+        let n = args.len() as u32;
+
+        match *self {
+            Term::Var(k) if k <= depth => Term::Var(k),
+            Term::Var(k) if k <= depth + n => args[(n - (k - depth)) as usize].shift(depth as i64, 0),
+            Term::Var(k) => Term::Var(k - n),
+            Term::Abs(m, ref body) => Term::Abs(m, Box::new(body.subst(args, depth + m as u32))),
+            Term::App(ref f, ref xs) => {
+                Term::App(Box::new(f.subst(args, depth)),
+                          xs.iter().map(|x| x.subst(args, depth)).collect())
+            }
+            Term::If(ref c, ref b1, ref b2) => {
+                Term::If(Box::new(c.subst(args, depth)),
+                         Box::new(b1.subst(args, depth)),
+                         Box::new(b2.subst(args, depth)))
+            }
+            ref t => t.clone(),
+        }
+    }
+}
+
+/// Call-by-value, left-to-right evaluation to a value.
+pub fn eval(t: &Term) -> Result<Term> {
+    match *t {
+        Term::Stuck => bail!("reached bottom"),
+        Term::If(ref c, ref b1, ref b2) => {
+            match eval(c)? {
+                Term::True => eval(b1),
+                Term::False => eval(b2),
+                c => bail!("if condition {} is not a boolean", c.unparse()),
+            }
+        }
+        Term::App(ref f, ref args) => {
+            let f = eval(f)?;
+            let args = args.iter().map(eval).collect::<Result<Vec<_>>>()?;
+
+            match f {
+                Term::Abs(n, ref body) if n == args.len() => eval(&body.subst(&args, 0)),
+                Term::Abs(n, _) => bail!("expected {} argument(s), found {}", n, args.len()),
+                Term::Not => {
+                    match (args.len(), args.first()) {
+                        (1, Some(&Term::True)) => Ok(Term::False),
+                        (1, Some(&Term::False)) => Ok(Term::True),
+                        _ => bail!("negated something other than one boolean"),
+                    }
+                }
+                f => bail!("applied a non-function {}", f.unparse()),
+            }
+        }
+        ref t => Ok(t.clone()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use slog::{Discard, Logger};
+
+    use super::*;
+    use corpus::{get, PROGRAMS};
+    use eval::Evaluator;
+
+    #[test]
+    fn test_erase() {
+        assert_eq!(erase(&get("(/lam x: #Int, t: #B. (if t x 0))")).unwrap().unparse(),
+                   "(/lam _, _. (If 1 2 0))");
+    }
+
+    #[test]
+    fn test_erasure_commutes() {
+        let mut ev = Evaluator::new(&Logger::root(Discard, o!()));
+
+        for code in PROGRAMS {
+            let t = get(code);
+            assert_eq!(eval(&erase(&t).unwrap()).unwrap(),
+                       erase(&ev.eval(&t).unwrap()).unwrap(),
+                       "{}",
+                       code);
+        }
+    }
+}
@@ -13,6 +13,8 @@ pub trait Visitor<'a>: Sized {
     fn visit_not(&mut self) {}
     fn visit_ty(&mut self, _: &'a Ty) {}
     fn visit_bottom(&mut self) {}
+    fn visit_hole(&mut self, _: &'a str) {}
+    fn visit_error(&mut self) {}
     fn visit_abs(&mut self, args: &'a [(String, Ty)], body: &'a Term) {
         walk_abs(self, args, body);
     }
@@ -85,6 +87,12 @@ pub fn walk_term<'a, V>(v: &mut V, term: &'a Term)
         &Term::Bottom => {
             v.visit_bottom();
         }
+        &Term::Hole(ref x) => {
+            v.visit_hole(x);
+        }
+        &Term::Error => {
+            v.visit_error();
+        }
         &Term::Int(n) => {
             v.visit_int(n);
         }